@@ -55,6 +55,13 @@ if [ $? -ne 0 ]; then
     exit 1
 fi
 
+echo "###### Copying selectors to the $TARGET..."
+sshpass -p "$1" ssh mimas@$HOST "sshpass -p "$1" rsync -azvP ~/prj/$PRJ/selectors.toml  mimas@$TARGET:/home/mimas/tmp/selectors.toml"
+if [ $? -ne 0 ]; then
+    echo "Error: Fail to copy the selectors to the target"
+    exit 1
+fi
+
 
 
 
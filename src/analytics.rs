@@ -0,0 +1,533 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+use crate::{
+    db::{listing::ListingRecord, market::MarketSample},
+    error::SSError,
+};
+
+/// Length of the weekly trend by default
+pub const WEEKS: usize = 8;
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// A listing missing on the search pages for so long is off the market, see
+/// `TimeOnMarket` for what the search pages limit
+const GONE_AFTER: Duration = Duration::hours(48);
+/// Groups in the bot message, the largest ones
+const MESSAGE_GROUPS: usize = 15;
+/// Fewer similar listings tell nothing about the price
+const MIN_COMPARABLES: usize = 5;
+/// Similar area is within this share of the area of the apartment
+const AREA_BAND: f64 = 0.2;
+
+/// Percentiles of the rent per m², €
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+}
+
+impl Distribution {
+    pub fn of(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        Some(Self {
+            count: values.len(),
+            p25: percentile(&values, 0.25),
+            median: percentile(&values, 0.5),
+            p75: percentile(&values, 0.75),
+        })
+    }
+}
+
+/// Linear interpolation between the closest ranks of the sorted values
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupStats {
+    pub district: String,
+    pub rooms: Option<i64>,
+    pub price_m2: Distribution,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WeekStats {
+    /// Monday of the week
+    pub week: NaiveDate,
+    /// Apartments which have appeared during the week
+    pub listings: usize,
+    pub price_m2: Option<Distribution>,
+}
+
+/// Time a listing stays on the search pages of the profiles, which is the
+/// time on the market only when the searches are not limited by the age of
+/// the ad: with the default ".../today-2/..." url every listing drops out
+/// after about two days, rented or not, and so does the median
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimeOnMarket {
+    /// Listings which are off the market, the time is known for them only
+    pub gone: usize,
+    pub active: usize,
+    pub mean_days: Option<f64>,
+    pub median_days: Option<f64>,
+}
+
+/// Reports built of the stored apartments
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketReport {
+    pub apartments: usize,
+    pub price_m2: Option<Distribution>,
+    /// By district and room count
+    pub groups: Vec<GroupStats>,
+    /// Oldest first
+    pub weeks: Vec<WeekStats>,
+    pub time_on_market: TimeOnMarket,
+}
+
+fn price_m2(sample: &MarketSample) -> Option<f64> {
+    match (sample.price_eur, sample.area) {
+        (Some(price), Some(area)) if price > 0 && area > 0.0 => Some(price as f64 / area),
+        _ => None,
+    }
+}
+
+fn monday(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FloorBand {
+    Ground,
+    Middle,
+    Top,
+}
+
+fn floor_band(sample: &MarketSample) -> Option<FloorBand> {
+    let floor = sample.floor?;
+    Some(if floor <= 1 {
+        FloorBand::Ground
+    } else if sample.total_floors == Some(floor) {
+        FloorBand::Top
+    } else {
+        FloorBand::Middle
+    })
+}
+
+/// Price per m² of an apartment against the similar listings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DealScore {
+    pub price_m2: f64,
+    pub median_m2: f64,
+    /// Share of the similar listings which are cheaper per m², 0..100
+    pub percentile: f64,
+    /// Below the median by that many percent, negative above it
+    pub discount: f64,
+    pub comparables: usize,
+    /// What the similar listings share with the apartment
+    pub basis: &'static str,
+}
+
+impl DealScore {
+    /// The narrowest set of similar listings which is large enough: same
+    /// district, rooms, area band and floor band, then fewer of them
+    pub fn of(apartment: &MarketSample, samples: &[MarketSample]) -> Option<Self> {
+        let own = price_m2(apartment)?;
+        let own_area = apartment.area?;
+        let same_district = |s: &MarketSample| s.district == apartment.district;
+        let same_rooms = |s: &MarketSample| s.rooms == apartment.rooms;
+        let same_area = |s: &MarketSample| {
+            s.area
+                .is_some_and(|a| (a - own_area).abs() <= own_area * AREA_BAND)
+        };
+        let same_floor = |s: &MarketSample| floor_band(s) == floor_band(apartment);
+        // Basis and whether the district, rooms, area and floor are the same
+        let levels = [
+            ("район, комнаты, площадь, этаж", [true, true, true, true]),
+            ("район, комнаты, площадь", [true, true, true, false]),
+            ("район, комнаты", [true, true, false, false]),
+            ("комнаты, площадь", [false, true, true, false]),
+        ];
+        let others: Vec<&MarketSample> = samples.iter().filter(|s| s.id != apartment.id).collect();
+        levels
+            .into_iter()
+            .find_map(|(basis, [district, rooms, area, floor])| {
+                let values: Vec<f64> = others
+                    .iter()
+                    .filter(|s| {
+                        (!district || same_district(s))
+                            && (!rooms || same_rooms(s))
+                            && (!area || same_area(s))
+                            && (!floor || same_floor(s))
+                    })
+                    .filter_map(|s| price_m2(s))
+                    .collect();
+                if values.len() < MIN_COMPARABLES {
+                    return None;
+                }
+                // Mid rank, the equal prices count as a half
+                let cheaper = values.iter().filter(|v| **v < own).count() as f64;
+                let equal = values.iter().filter(|v| **v == own).count() as f64;
+                let percentile = (cheaper + equal / 2.0) / values.len() as f64 * 100.0;
+                let median_m2 = Distribution::of(values.clone())?.median;
+                Some(Self {
+                    price_m2: own,
+                    median_m2,
+                    percentile,
+                    discount: (median_m2 - own) / median_m2 * 100.0,
+                    comparables: values.len(),
+                    basis,
+                })
+            })
+    }
+
+    /// Line of the notification
+    pub fn message(&self) -> String {
+        let relative = if self.discount >= 0.0 {
+            format!("на {:.0}% дешевле", self.discount)
+        } else {
+            format!("на {:.0}% дороже", -self.discount)
+        };
+        format!(
+            "оценка: {:.1} €/м², {} медианы {:.1}, дешевле {:.0}% похожих ({} шт.: {})",
+            self.price_m2,
+            relative,
+            self.median_m2,
+            100.0 - self.percentile,
+            self.comparables,
+            self.basis
+        )
+    }
+}
+
+impl MarketReport {
+    /// Reads the database, `weeks` is the length of the weekly trend
+    pub fn collect(profile: Option<&str>, weeks: usize) -> Result<Self, SSError> {
+        let samples = MarketSample::select(profile)?;
+        let lifetimes = ListingRecord::lifetimes()?;
+        let lifetimes: Vec<(NaiveDateTime, NaiveDateTime)> = lifetimes
+            .iter()
+            .filter_map(|(first, last)| {
+                Some((
+                    NaiveDateTime::parse_from_str(first, DATETIME_FORMAT).ok()?,
+                    NaiveDateTime::parse_from_str(last, DATETIME_FORMAT).ok()?,
+                ))
+            })
+            .collect();
+        Ok(Self::build(
+            &samples,
+            &lifetimes,
+            chrono::Local::now().naive_local(),
+            chrono::Utc::now().naive_utc(),
+            weeks,
+        ))
+    }
+
+    /// `now` is the local time of the samples, `now_utc` of the lifetimes
+    pub fn build(
+        samples: &[MarketSample],
+        lifetimes: &[(NaiveDateTime, NaiveDateTime)],
+        now: NaiveDateTime,
+        now_utc: NaiveDateTime,
+        weeks: usize,
+    ) -> Self {
+        let mut groups: BTreeMap<(String, Option<i64>), Vec<f64>> = BTreeMap::new();
+        let first_week = monday(now.date()) - Duration::weeks(weeks.saturating_sub(1) as i64);
+        let mut by_week: BTreeMap<NaiveDate, (usize, Vec<f64>)> = (0..weeks)
+            .map(|w| (first_week + Duration::weeks(w as i64), (0, vec![])))
+            .collect();
+        for sample in samples {
+            let value = price_m2(sample);
+            if let Some(value) = value {
+                let district = sample.district.clone().unwrap_or_default();
+                groups
+                    .entry((district, sample.rooms))
+                    .or_default()
+                    .push(value);
+            }
+            let sent = NaiveDateTime::parse_from_str(&sample.first_sent, DATETIME_FORMAT);
+            if let Some(week) = sent.ok().and_then(|t| by_week.get_mut(&monday(t.date()))) {
+                week.0 += 1;
+                week.1.extend(value);
+            }
+        }
+
+        let mut time_on_market = TimeOnMarket::default();
+        let mut days = vec![];
+        for (first, last) in lifetimes {
+            if now_utc - *last < GONE_AFTER {
+                time_on_market.active += 1;
+            } else {
+                time_on_market.gone += 1;
+                days.push((*last - *first).num_minutes().max(0) as f64 / (24.0 * 60.0));
+            }
+        }
+        if !days.is_empty() {
+            time_on_market.mean_days = Some(days.iter().sum::<f64>() / days.len() as f64);
+            time_on_market.median_days = Distribution::of(days).map(|d| d.median);
+        }
+
+        Self {
+            apartments: samples.len(),
+            price_m2: Distribution::of(samples.iter().filter_map(price_m2).collect()),
+            groups: groups
+                .into_iter()
+                .filter_map(|((district, rooms), values)| {
+                    Some(GroupStats {
+                        district,
+                        rooms,
+                        price_m2: Distribution::of(values)?,
+                    })
+                })
+                .collect(),
+            weeks: by_week
+                .into_iter()
+                .map(|(week, (listings, values))| WeekStats {
+                    week,
+                    listings,
+                    price_m2: Distribution::of(values),
+                })
+                .collect(),
+            time_on_market,
+        }
+    }
+
+    /// Text of the /stats answer
+    pub fn message(&self) -> String {
+        let mut lines = vec![format!("Квартир в базе: {}", self.apartments)];
+        match self.price_m2.as_ref() {
+            Some(d) => lines.push(format!(
+                "Медиана: {:.1} €/м² (25–75%: {:.1}–{:.1}, по {} шт.)",
+                d.median, d.p25, d.p75, d.count
+            )),
+            None => lines.push("Нет квартир с ценой и площадью".to_string()),
+        }
+        if !self.groups.is_empty() {
+            lines.push(String::new());
+            lines.push("По районам и комнатам, €/м²:".to_string());
+            let mut groups: Vec<&GroupStats> = self.groups.iter().collect();
+            groups.sort_by_key(|g| std::cmp::Reverse(g.price_m2.count));
+            for g in groups.iter().take(MESSAGE_GROUPS) {
+                lines.push(format!(
+                    "{}, {}: {:.1} ({:.1}–{:.1}), {} шт.",
+                    if g.district.is_empty() {
+                        "—"
+                    } else {
+                        &g.district
+                    },
+                    g.rooms.map_or("? к.".to_string(), |r| format!("{} к.", r)),
+                    g.price_m2.median,
+                    g.price_m2.p25,
+                    g.price_m2.p75,
+                    g.price_m2.count
+                ));
+            }
+            if groups.len() > MESSAGE_GROUPS {
+                lines.push(format!("и ещё {} групп", groups.len() - MESSAGE_GROUPS));
+            }
+        }
+        if !self.weeks.is_empty() {
+            lines.push(String::new());
+            lines.push("По неделям: новых, медиана €/м²".to_string());
+            for w in self.weeks.iter() {
+                lines.push(format!(
+                    "{}: {}, {}",
+                    w.week.format("%d.%m"),
+                    w.listings,
+                    w.price_m2
+                        .as_ref()
+                        .map_or("—".to_string(), |d| format!("{:.1}", d.median))
+                ));
+            }
+        }
+        let t = &self.time_on_market;
+        lines.push(String::new());
+        match (t.mean_days, t.median_days) {
+            (Some(mean), Some(median)) => lines.push(format!(
+                "Время в поиске: в среднем {:.1} дн., медиана {:.1} дн. (снято {}, активно {})",
+                mean, median, t.gone, t.active
+            )),
+            _ => lines.push(format!(
+                "Время в поиске: пока неизвестно (активно {})",
+                t.active
+            )),
+        }
+        lines.join("\n")
+    }
+}
+
+impl Display for MarketReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "apartments: {}", self.apartments)?;
+        if let Some(d) = self.price_m2.as_ref() {
+            writeln!(
+                f,
+                "€/m²: median {:.1}, p25 {:.1}, p75 {:.1}, n {}",
+                d.median, d.p25, d.p75, d.count
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<24} {:>5} {:>5} {:>7} {:>7} {:>7}",
+            "district", "rooms", "n", "p25", "median", "p75"
+        )?;
+        for g in self.groups.iter() {
+            writeln!(
+                f,
+                "{:<24} {:>5} {:>5} {:>7.1} {:>7.1} {:>7.1}",
+                if g.district.is_empty() {
+                    "-"
+                } else {
+                    &g.district
+                },
+                g.rooms.map_or("-".to_string(), |r| r.to_string()),
+                g.price_m2.count,
+                g.price_m2.p25,
+                g.price_m2.median,
+                g.price_m2.p75
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<10} {:>5} {:>7}", "week", "new", "median")?;
+        for w in self.weeks.iter() {
+            writeln!(
+                f,
+                "{:<10} {:>5} {:>7}",
+                w.week.to_string(),
+                w.listings,
+                w.price_m2
+                    .as_ref()
+                    .map_or("-".to_string(), |d| format!("{:.1}", d.median))
+            )?;
+        }
+        writeln!(f)?;
+        let t = &self.time_on_market;
+        write!(
+            f,
+            "time on the search pages: mean {} days, median {} days, {} gone, {} active",
+            t.mean_days.map_or("-".to_string(), |d| format!("{:.1}", d)),
+            t.median_days
+                .map_or("-".to_string(), |d| format!("{:.1}", d)),
+            t.gone,
+            t.active
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, DATETIME_FORMAT).unwrap()
+    }
+
+    fn sample(sent: &str, price: i64, area: f64, rooms: i64, district: &str) -> MarketSample {
+        MarketSample {
+            id: format!("{}{}", sent, price),
+            first_sent: sent.to_string(),
+            price_eur: Some(price),
+            area: Some(area),
+            rooms: Some(rooms),
+            district: Some(district.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn flat(id: &str, price: i64, area: f64, floor: i64) -> MarketSample {
+        MarketSample {
+            id: id.to_string(),
+            price_eur: Some(price),
+            area: Some(area),
+            rooms: Some(2),
+            district: Some("Центр".to_string()),
+            floor: Some(floor),
+            total_floors: Some(5),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scores_deals() {
+        // 10, 11, .. 15 €/m² on the middle floors, cheaper on the ground floor
+        let mut samples: Vec<MarketSample> = (0..6)
+            .map(|i| flat(&i.to_string(), 500 + i * 50, 50.0, 3))
+            .collect();
+        samples.extend((0..6).map(|i| flat(&format!("g{}", i), 300, 50.0, 1)));
+        let score = DealScore::of(&flat("new", 525, 50.0, 3), &samples).unwrap();
+        assert_eq!(score.basis, "район, комнаты, площадь, этаж");
+        assert_eq!(score.comparables, 6);
+        assert_eq!(score.median_m2, 12.5);
+        assert!((score.percentile - 100.0 / 6.0).abs() < 1e-9);
+        assert!((score.discount - 16.0).abs() < 1e-9);
+        // Top floor has no similar listings, the floor is ignored then
+        let score = DealScore::of(&flat("top", 500, 50.0, 5), &samples).unwrap();
+        assert_eq!(score.basis, "район, комнаты, площадь");
+        assert_eq!(score.comparables, 12);
+        // The apartment itself is not compared
+        assert!(DealScore::of(&samples[0], &samples[..5]).is_none());
+        assert!(DealScore::of(&flat("big", 500, 120.0, 3), &samples[..4]).is_none());
+    }
+
+    #[test]
+    fn computes_percentiles() {
+        assert_eq!(percentile(&[1.0], 0.5), 1.0);
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 0.5), 2.5);
+        let d = Distribution::of(vec![40.0, 10.0, 30.0, 20.0, 50.0]).unwrap();
+        assert_eq!((d.p25, d.median, d.p75), (20.0, 30.0, 40.0));
+        assert!(Distribution::of(vec![]).is_none());
+    }
+
+    #[test]
+    fn builds_report() {
+        let samples = [
+            sample("2023-06-05 10:00:00", 500, 50.0, 2, "Центр"),
+            sample("2023-06-07 10:00:00", 700, 50.0, 2, "Центр"),
+            sample("2023-06-13 10:00:00", 300, 30.0, 1, "Тейка"),
+            MarketSample {
+                id: "no area".to_string(),
+                first_sent: "2023-06-13 11:00:00".to_string(),
+                price_eur: Some(400),
+                ..Default::default()
+            },
+        ];
+        let lifetimes = [
+            (time("2023-06-01 10:00:00"), time("2023-06-05 10:00:00")),
+            (time("2023-06-01 10:00:00"), time("2023-06-03 10:00:00")),
+            (time("2023-06-10 10:00:00"), time("2023-06-14 10:00:00")),
+        ];
+        let now = time("2023-06-14 12:00:00");
+        let report = MarketReport::build(&samples, &lifetimes, now, now, 3);
+        assert_eq!(report.apartments, 4);
+        assert_eq!(report.price_m2.as_ref().unwrap().count, 3);
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[1].district, "Центр");
+        assert_eq!(report.groups[1].price_m2.median, 12.0);
+        let weeks: Vec<(String, usize)> = report
+            .weeks
+            .iter()
+            .map(|w| (w.week.to_string(), w.listings))
+            .collect();
+        assert_eq!(
+            weeks,
+            [
+                ("2023-05-29".to_string(), 0),
+                ("2023-06-05".to_string(), 2),
+                ("2023-06-12".to_string(), 2)
+            ]
+        );
+        let t = &report.time_on_market;
+        assert_eq!((t.gone, t.active), (2, 1));
+        assert_eq!(t.mean_days, Some(3.0));
+        assert!(report.message().contains("Центр, 2 к.: 12.0"));
+    }
+}
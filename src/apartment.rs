@@ -1,30 +1,142 @@
-use crate::db::record::ApartmentRecrod;
+use crate::{commute::Commute, db::record::ApartmentRecrod, description::Deposit};
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Location {
     pub latitude: f64,
     pub longitude: f64,
+    pub source: LocationSource,
+    pub precision: Precision,
 }
 
-#[derive(Debug, Clone, Default)]
+impl Location {
+    /// Pin of the ss.lv map
+    pub fn exact(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            source: LocationSource::Map,
+            precision: Precision::Exact,
+        }
+    }
+}
+
+/// Where the coordinates come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationSource {
+    /// Map link of the ad
+    #[default]
+    Map,
+    Nominatim,
+    /// Offline address dataset
+    Dataset,
+}
+
+/// What the coordinates point at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    #[default]
+    Exact,
+    House,
+    Street,
+    District,
+    City,
+}
+
+impl LocationSource {
+    pub const ALL: [Self; 3] = [Self::Map, Self::Nominatim, Self::Dataset];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Map => "map",
+            Self::Nominatim => "nominatim",
+            Self::Dataset => "dataset",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.as_str() == s)
+    }
+}
+
+impl Precision {
+    pub const ALL: [Self; 5] = [
+        Self::Exact,
+        Self::House,
+        Self::Street,
+        Self::District,
+        Self::City,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::House => "house",
+            Self::Street => "street",
+            Self::District => "district",
+            Self::City => "city",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|precision| precision.as_str() == s)
+    }
+
+    /// Marker of the approximate distance in the messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Exact => "",
+            Self::House => "по адресу",
+            Self::Street => "по улице",
+            Self::District => "по району",
+            Self::City => "по городу",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ApartmentDescription {
     pub park: bool,
     pub elevator: bool,
     pub balkony: bool,
+    pub pets: Option<bool>,
+    pub furnished: Option<bool>,
+    pub dishwasher: bool,
+    pub commission: Option<bool>,
+    pub deposit: Option<Deposit>,
 }
 
 impl ApartmentDescription {
-    pub fn new(park: bool, elevator: bool, balkony: bool) -> Self {
-        Self {
-            park,
-            balkony,
-            elevator,
-        }
+    pub fn brief(&self) -> String {
+        let flag = |f: bool| if f { "+" } else { "-" };
+        let opt = |f: Option<bool>| f.map_or("?", flag);
+        format!(
+            "л:{}, п:{}, б:{}, жив:{}, меб:{}, пмм:{}, деп:{}",
+            flag(self.elevator),
+            flag(self.park),
+            flag(self.balkony),
+            opt(self.pets),
+            opt(self.furnished),
+            flag(self.dishwasher),
+            self.deposit.map_or("?".to_string(), |d| d.to_string()),
+        )
     }
 }
 
-#[derive(Default, Debug, Builder, Clone)]
+/// Contacts block of an ad
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SellerInfo {
+    pub company: Option<String>,
+    pub is_agency: bool,
+    pub has_phone: bool,
+}
+
+#[derive(Default, Debug, Builder, Clone, Serialize)]
 pub struct Apartment {
     pub url: String,
     pub id: String,
@@ -37,22 +149,162 @@ pub struct Apartment {
     pub location: Option<Location>,
     #[builder(default)]
     pub distance: Option<i64>,
+    /// Travel times to the configured destinations, known ones only
+    #[builder(default)]
+    pub commutes: Vec<Commute>,
     pub rooms: u64,
     pub area: f64,
     pub floor: Option<i64>,
+    #[builder(default)]
+    pub total_floors: Option<i64>,
+    #[builder(default)]
+    pub is_ground_floor: bool,
+    #[builder(default)]
+    pub is_top_floor: bool,
     pub elevator: bool,
     pub parking: bool,
     #[builder(default)]
     pub description: Option<ApartmentDescription>,
+    /// Text of the ad as is
+    #[builder(default)]
+    pub description_text: String,
+    #[builder(default)]
+    pub seller: Option<SellerInfo>,
+    #[builder(default)]
+    pub is_agency: bool,
+    #[builder(default)]
+    pub commission: Option<bool>,
+    #[builder(default)]
+    pub phones: Vec<String>,
+}
+
+impl Apartment {
+    /// Monthly price in EUR out of the "600 €/мес." text
+    pub fn price_eur(&self) -> Option<u32> {
+        let number = self.price.split('€').next().unwrap_or_default();
+        ListingSummary::parse_price(number)
+    }
+
+    /// Elevator is mentioned either in the floor line or in the description
+    pub fn has_elevator(&self) -> bool {
+        self.elevator || self.description.as_ref().is_some_and(|d| d.elevator)
+    }
+
+    /// "агент" or "собств." followed by the company name and commission flag, e.g. "агент(Re/Max), ком:+"
+    pub fn seller_brief(&self) -> String {
+        let company = self
+            .seller
+            .as_ref()
+            .and_then(|s| s.company.as_ref())
+            .map_or(String::new(), |c| format!("({})", c));
+        let commission = self.commission.map_or("?", |c| if c { "+" } else { "-" });
+        if self.is_agency {
+            format!("агент{}, ком:{}", company, commission)
+        } else {
+            format!("собств.{}, ком:{}", company, commission)
+        }
+    }
+
+    /// Floor in the "floor/total" form with a marker of the first/last floor, e.g. "5/5 (посл.)"
+    pub fn floor_brief(&self) -> String {
+        let floor = self.floor.map_or("-".to_string(), |f| f.to_string());
+        let total = self.total_floors.map_or("-".to_string(), |t| t.to_string());
+        let marker = if self.is_ground_floor {
+            " (перв.)"
+        } else if self.is_top_floor {
+            " (посл.)"
+        } else {
+            ""
+        };
+        format!("{}/{}{}", floor, total, marker)
+    }
+
+    /// "1200 м", "1200 м (по улице)" or "?" without the coordinates
+    pub fn distance_brief(&self) -> String {
+        let Some(distance) = self.distance else {
+            return "?".to_string();
+        };
+        match self.location.as_ref().map(|l| l.precision.label()) {
+            Some(label) if !label.is_empty() => format!("{} м ({})", distance, label),
+            _ => format!("{} м", distance),
+        }
+    }
+}
+
+/// Row of the search results, known before the ad page is fetched
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ListingSummary {
+    pub id: String,
+    pub url: String,
+    pub thumbnail: Option<String>,
+    pub text: String,
+    pub district: Option<String>,
+    pub street: Option<String>,
+    pub rooms: Option<f64>,
+    pub area: Option<f64>,
+    pub floor: Option<i64>,
+    pub total_floors: Option<i64>,
+    pub series: Option<String>,
+    /// EUR per month
+    pub price: Option<u32>,
+}
+
+impl ListingSummary {
+    /// "1,200" or "450 " into the number
+    pub fn parse_price(raw: &str) -> Option<u32> {
+        let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    }
+
+    pub fn is_ground_floor(&self) -> bool {
+        self.floor == Some(1)
+    }
+
+    pub fn brief(&self) -> String {
+        let opt = |v: Option<String>| v.unwrap_or("-".to_string());
+        format!(
+            "цена:{}, комн:{}, пл.:{} м2, этаж:{}/{}, {}, серия:{}",
+            opt(self.price.map(|p| p.to_string())),
+            opt(self.rooms.map(|r| r.to_string())),
+            opt(self.area.map(|a| a.to_string())),
+            opt(self.floor.map(|f| f.to_string())),
+            opt(self.total_floors.map(|t| t.to_string())),
+            [self.district.as_deref(), self.street.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", "),
+            opt(self.series.clone()),
+        )
+    }
 }
 
 impl From<Apartment> for ApartmentRecrod {
     fn from(value: Apartment) -> Self {
         let mut record = ApartmentRecrod::new();
+        record.price_eur.value = value.price_eur().map(|p| p as i64);
         record.id.value = value.id;
         record.datetime.value = value.datetime.to_string();
         record.price.value = value.price;
         record.url.value = value.url;
+        record.rooms.value = Some(value.rooms as i64);
+        record.area.value = Some(value.area);
+        record.district.value = Some(value.district).filter(|d| !d.is_empty());
+        record.address.value = Some(value.address).filter(|a| !a.is_empty());
+        record.floor.value = value.floor;
+        record.total_floors.value = value.total_floors;
+        record.latitude.value = value.location.as_ref().map(|l| l.latitude);
+        record.longitude.value = value.location.as_ref().map(|l| l.longitude);
+        record.location_source.value = value
+            .location
+            .as_ref()
+            .map(|l| l.source.as_str().to_string());
+        record.location_precision.value = value
+            .location
+            .as_ref()
+            .map(|l| l.precision.as_str().to_string());
+        record.distance.value = value.distance;
+        record.description.value = Some(value.description_text).filter(|d| !d.is_empty());
         record
     }
 }
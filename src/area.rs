@@ -0,0 +1,274 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    apartment::{Apartment, Precision},
+    config::Config,
+    db::profile::SearchProfile,
+    error::SSError,
+};
+
+static DEFAULT_AREAS: &str = include_str!("../riga_areas.geojson");
+static AREAS: OnceLock<BTreeMap<String, Area>> = OnceLock::new();
+
+/// Coordinates coarser than this are as good as none for the areas
+const MIN_PRECISION: Precision = Precision::Street;
+
+/// What to do with an apartment without the coordinates when the profile
+/// has areas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationPolicy {
+    #[default]
+    Pass,
+    Reject,
+}
+
+/// Rings of longitude, latitude; the first one is the outer boundary, the
+/// others are the holes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon(Vec<Vec<(f64, f64)>>);
+
+impl Polygon {
+    /// Even-odd rule, the holes fall out of it
+    pub fn contains(&self, longitude: f64, latitude: f64) -> bool {
+        let mut inside = false;
+        for ring in self.0.iter() {
+            for (i, &(x1, y1)) in ring.iter().enumerate() {
+                let (x2, y2) = ring[(i + 1) % ring.len()];
+                if (y1 > latitude) != (y2 > latitude)
+                    && longitude < x1 + (latitude - y1) * (x2 - x1) / (y2 - y1)
+                {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+/// Named polygons, a neighbourhood or "east of the river"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Area {
+    pub name: String,
+    pub polygons: Vec<Polygon>,
+}
+
+impl Area {
+    pub fn contains(&self, longitude: f64, latitude: f64) -> bool {
+        self.polygons
+            .iter()
+            .any(|p| p.contains(longitude, latitude))
+    }
+
+    /// Polygon, MultiPolygon, Feature or FeatureCollection; a feature is
+    /// named by its "name" property, the unnamed ones get `name`
+    pub fn parse_geojson(text: &str, name: &str) -> Result<Vec<Self>, SSError> {
+        let value: Value = serde_json::from_str(text)?;
+        let mut areas = vec![];
+        Self::collect(&value, name, &mut areas)?;
+        if areas.is_empty() {
+            return Err(SSError::Request("GeoJSON: no polygons".into()));
+        }
+        Ok(areas)
+    }
+
+    fn collect(value: &Value, name: &str, areas: &mut Vec<Self>) -> Result<(), SSError> {
+        match value["type"].as_str() {
+            Some("FeatureCollection") => {
+                for feature in value["features"].as_array().into_iter().flatten() {
+                    Self::collect(feature, name, areas)?;
+                }
+            }
+            Some("Feature") => {
+                let name = value["properties"]["name"].as_str().unwrap_or(name);
+                Self::collect(&value["geometry"], name, areas)?;
+            }
+            Some("Polygon") => areas.push(Self {
+                name: name.to_string(),
+                polygons: vec![polygon(&value["coordinates"])?],
+            }),
+            Some("MultiPolygon") => areas.push(Self {
+                name: name.to_string(),
+                polygons: value["coordinates"]
+                    .as_array()
+                    .ok_or(SSError::Request("GeoJSON: no coordinates".into()))?
+                    .iter()
+                    .map(polygon)
+                    .collect::<Result<_, _>>()?,
+            }),
+            other => {
+                return Err(SSError::Request(format!(
+                    "GeoJSON: unsupported type {}",
+                    other.unwrap_or("none")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Shipped Riga areas and the ones of `filter.areas_file`, which replace
+    /// the shipped ones of the same name; only four rough outlines are
+    /// shipped, real neighbourhoods come from the file
+    pub fn named() -> Result<&'static BTreeMap<String, Self>, SSError> {
+        if let Some(areas) = AREAS.get() {
+            return Ok(areas);
+        }
+        let mut areas = BTreeMap::new();
+        for area in Self::parse_geojson(DEFAULT_AREAS, "")? {
+            areas.insert(area.name.clone(), area);
+        }
+        let path = &Config::settings().filter.areas_file;
+        if !path.is_empty() {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| SSError::Config(format!("{}: {}", path, e)))?;
+            let custom = Self::parse_geojson(&text, "")
+                .map_err(|e| SSError::Config(format!("{}: {}", path, e)))?;
+            for area in custom {
+                if area.name.is_empty() {
+                    return Err(SSError::Config(format!("{}: unnamed feature", path)));
+                }
+                areas.insert(area.name.clone(), area);
+            }
+            log::info!("Using areas from '{}'", path);
+        }
+        Ok(AREAS.get_or_init(|| areas))
+    }
+}
+
+/// `[[[lon, lat], ...], ...]`
+fn polygon(coordinates: &Value) -> Result<Polygon, SSError> {
+    let rings = coordinates
+        .as_array()
+        .filter(|rings| !rings.is_empty())
+        .ok_or(SSError::Request("GeoJSON: polygon without rings".into()))?;
+    let mut polygon = vec![];
+    for ring in rings {
+        let points = ring
+            .as_array()
+            .ok_or(SSError::Request("GeoJSON: ring is not an array".into()))?
+            .iter()
+            .map(|point| match (point[0].as_f64(), point[1].as_f64()) {
+                (Some(lon), Some(lat)) => Ok((lon, lat)),
+                _ => Err(SSError::Request(format!(
+                    "GeoJSON: invalid point {}",
+                    point
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if points.len() < 4 {
+            return Err(SSError::Request(
+                "GeoJSON: ring of less than 4 points".into(),
+            ));
+        }
+        polygon.push(points);
+    }
+    Ok(Polygon(polygon))
+}
+
+/// Areas of a search profile, the apartment has to be inside one of them
+#[derive(Debug, Clone)]
+pub struct AreaFilter {
+    areas: Vec<Area>,
+    without_location: LocationPolicy,
+}
+
+impl AreaFilter {
+    /// None when the profile has no areas; unknown names and invalid
+    /// polygons are errors
+    pub fn for_profile(profile: &SearchProfile) -> Result<Option<Self>, SSError> {
+        let mut areas = vec![];
+        let names = profile.area_names();
+        if !names.is_empty() {
+            let named = Area::named()?;
+            for name in names {
+                let area = named.get(&name).ok_or(SSError::field("areas", &name))?;
+                areas.push(area.clone());
+            }
+        }
+        if let Some(polygons) = profile.polygons.value.as_deref() {
+            areas.extend(Area::parse_geojson(polygons, "polygon")?);
+        }
+        if areas.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            areas,
+            without_location: Config::area_without_location(),
+        }))
+    }
+
+    /// None if the apartment is inside, or has no coordinates and they pass
+    pub fn reject_reason(&self, a: &Apartment) -> Option<String> {
+        let Some(location) = a.location.as_ref().filter(|l| l.precision <= MIN_PRECISION) else {
+            return match self.without_location {
+                LocationPolicy::Pass => None,
+                LocationPolicy::Reject => Some("no coordinates for the areas".to_string()),
+            };
+        };
+        if self
+            .areas
+            .iter()
+            .any(|area| area.contains(location.longitude, location.latitude))
+        {
+            return None;
+        }
+        Some(format!(
+            "outside of {}",
+            self.areas
+                .iter()
+                .map(|area| area.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apartment::Location;
+
+    #[test]
+    fn contains_points() {
+        // Square with a square hole
+        let text = r#"{"type": "Polygon", "coordinates": [
+            [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+            [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+        ]}"#;
+        let areas = Area::parse_geojson(text, "square").unwrap();
+        assert_eq!(areas[0].name, "square");
+        assert!(areas[0].contains(2.0, 2.0));
+        assert!(!areas[0].contains(5.0, 5.0));
+        assert!(!areas[0].contains(11.0, 2.0));
+        assert!(Area::parse_geojson(r#"{"type": "Point", "coordinates": [1, 2]}"#, "").is_err());
+    }
+
+    #[test]
+    fn ships_riga_areas() {
+        let areas = Area::parse_geojson(DEFAULT_AREAS, "").unwrap();
+        let area = |name: &str| areas.iter().find(|a| a.name == name).unwrap();
+        // Doms, Old Town on the right bank
+        assert!(area("vecriga").contains(24.1052, 56.9490));
+        assert!(area("labais_krasts").contains(24.1052, 56.9490));
+        assert!(!area("pardaugava").contains(24.1052, 56.9490));
+        // Āgenskalns
+        assert!(area("pardaugava").contains(24.0700, 56.9400));
+    }
+
+    #[test]
+    fn rejects_outside() {
+        let filter = AreaFilter {
+            areas: Area::parse_geojson(DEFAULT_AREAS, "").unwrap(),
+            without_location: LocationPolicy::Reject,
+        };
+        let mut a = Apartment::default();
+        assert!(filter.reject_reason(&a).is_some());
+        a.location = Some(Location::exact(56.9490, 24.1052));
+        assert!(filter.reject_reason(&a).is_none());
+        a.location = Some(Location::exact(56.80, 24.50));
+        assert!(filter.reject_reason(&a).unwrap().starts_with("outside of"));
+    }
+}
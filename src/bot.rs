@@ -0,0 +1,213 @@
+use std::{collections::HashMap, sync::Arc};
+
+use teloxide::{prelude::*, types::InputFile};
+
+use crate::{
+    analytics::{MarketReport, WEEKS},
+    db::record::RecordQuery,
+    error::SSError,
+    export::{Format, Listing},
+};
+
+const HELP: &str = "Команды:
+/export csv|jsonl|geojson [profile=… status=… price_min=… price_max=… area_min=… area_max=… district=… distance_max=…] — сохранённые квартиры файлом
+/stats [profile=… weeks=…] — цены за м² по районам, динамика по неделям и время в поиске
+/help — эта справка";
+
+const MAX_WEEKS: usize = 104;
+
+/// Commands accepted in the chats of the bot
+#[derive(Debug)]
+pub enum BotCommand {
+    Help,
+    /// The stored apartments matching the query as a file
+    Export {
+        format: Format,
+        query: RecordQuery,
+    },
+    /// Market report of the stored apartments
+    Stats {
+        profile: Option<String>,
+        weeks: usize,
+    },
+}
+
+impl BotCommand {
+    /// None if the text is not a command, "/export@rentbot csv" is one
+    pub fn parse(text: &str) -> Option<Result<Self, SSError>> {
+        let mut words = text.split_whitespace();
+        let command = words.next()?.strip_prefix('/')?;
+        let command = command.split('@').next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        match command {
+            "help" | "start" => Some(Ok(Self::Help)),
+            "export" => Some(Self::export(&args)),
+            "stats" => Some(Self::stats(&args)),
+            _ => None,
+        }
+    }
+
+    /// The format and then the filters of the dashboard, "key=value"
+    fn export(args: &[&str]) -> Result<Self, SSError> {
+        let format = match args.first() {
+            Some(format) => Format::parse(format)?,
+            None => Format::Csv,
+        };
+        Ok(Self::Export {
+            format,
+            query: RecordQuery::from_params(&params(args.get(1..).unwrap_or_default())?)?,
+        })
+    }
+
+    fn stats(args: &[&str]) -> Result<Self, SSError> {
+        let params = params(args)?;
+        let weeks = match params.get("weeks") {
+            Some(weeks) => weeks
+                .parse()
+                .ok()
+                .filter(|w| (1..=MAX_WEEKS).contains(w))
+                .ok_or(SSError::field("weeks", weeks))?,
+            None => WEEKS,
+        };
+        Ok(Self::Stats {
+            profile: params.get("profile").cloned(),
+            weeks,
+        })
+    }
+}
+
+/// Arguments "key=value"
+fn params(args: &[&str]) -> Result<HashMap<String, String>, SSError> {
+    args.iter()
+        .map(|arg| {
+            arg.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or(SSError::Request(format!("expected key=value: '{}'", arg)))
+        })
+        .collect()
+}
+
+/// Answers the commands sent to the given chats, the others are ignored;
+/// returns when the bot is stopped
+pub async fn listen(bot: Bot, chats: Vec<String>) {
+    let handler = Update::filter_message().endpoint(handle);
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![Arc::new(chats)])
+        .default_handler(|_| async {})
+        .build()
+        .dispatch()
+        .await;
+}
+
+/// Chat id or "@username", as in TELOXIDE_CHAT_ID
+fn allowed(chat: &teloxide::types::Chat, chats: &[String]) -> bool {
+    chats.iter().any(|allowed| {
+        *allowed == chat.id.to_string()
+            || chat
+                .username()
+                .is_some_and(|name| allowed.strip_prefix('@') == Some(name))
+    })
+}
+
+async fn handle(bot: Bot, msg: Message, chats: Arc<Vec<String>>) -> Result<(), SSError> {
+    let Some(command) = msg.text().and_then(BotCommand::parse) else {
+        return Ok(());
+    };
+    if !allowed(&msg.chat, &chats) {
+        log::warn!("Ignore a command of the unknown chat {}", msg.chat.id);
+        return Ok(());
+    }
+    match command {
+        Ok(BotCommand::Help) => {
+            bot.send_message(msg.chat.id, HELP).await?;
+        }
+        Ok(BotCommand::Export { format, query }) => {
+            let exported = tokio::task::spawn_blocking(move || {
+                let listings = Listing::select(&query)?;
+                let mut file = vec![];
+                format.write(&listings, &mut file)?;
+                Ok::<_, SSError>((listings.len(), file))
+            })
+            .await
+            .map_err(|e| SSError::Web(e.to_string()))?;
+            match exported {
+                Ok((0, _)) => {
+                    bot.send_message(msg.chat.id, "Нет подходящих квартир")
+                        .await?;
+                }
+                Ok((count, file)) => {
+                    log::info!(
+                        "Exported {} apartment(s) to the chat {}",
+                        count,
+                        msg.chat.id
+                    );
+                    let name = format!("listings.{}", format.as_str());
+                    bot.send_document(msg.chat.id, InputFile::memory(file).file_name(name))
+                        .caption(format!("Квартир: {}", count))
+                        .await?;
+                }
+                Err(e) => {
+                    log::error!("Export has failed: {}", e);
+                    bot.send_message(msg.chat.id, format!("Не удалось выгрузить: {}", e))
+                        .await?;
+                }
+            }
+        }
+        Ok(BotCommand::Stats { profile, weeks }) => {
+            let report = tokio::task::spawn_blocking(move || {
+                MarketReport::collect(profile.as_deref(), weeks)
+            })
+            .await
+            .map_err(|e| SSError::Web(e.to_string()))?;
+            let text = match report {
+                Ok(report) => report.message(),
+                Err(e) => {
+                    log::error!("Market report has failed: {}", e);
+                    format!("Не удалось посчитать: {}", e)
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("Ошибка: {}\n\n{}", e, HELP))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert!(BotCommand::parse("hello").is_none());
+        assert!(BotCommand::parse("/unknown").is_none());
+        match BotCommand::parse("/export@rentbot geojson profile=home price_max=600") {
+            Some(Ok(BotCommand::Export { format, query })) => {
+                assert_eq!(format, Format::GeoJson);
+                assert_eq!(query.profile.as_deref(), Some("home"));
+                assert_eq!(query.price_max, Some(600));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match BotCommand::parse("/export") {
+            Some(Ok(BotCommand::Export { format, .. })) => assert_eq!(format, Format::Csv),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(BotCommand::parse("/export xlsx"), Some(Err(_))));
+        assert!(matches!(
+            BotCommand::parse("/stats weeks=4"),
+            Some(Ok(BotCommand::Stats {
+                profile: None,
+                weeks: 4
+            }))
+        ));
+        assert!(matches!(BotCommand::parse("/stats weeks=0"), Some(Err(_))));
+        assert!(matches!(
+            BotCommand::parse("/export csv home"),
+            Some(Err(_))
+        ));
+    }
+}
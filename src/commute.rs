@@ -0,0 +1,351 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{apartment::Location, config::Config, db::commute::CommuteRecord, error::SSError};
+
+/// Self-hosted routing engine
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// Serves a single profile, see `Destination::url`; no transit
+    Osrm,
+    /// Transit needs the transit tiles built out of GTFS feeds
+    Valhalla,
+}
+
+impl Engine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Osrm => "osrm",
+            Self::Valhalla => "valhalla",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TravelMode {
+    #[default]
+    Walk,
+    Bicycle,
+    Car,
+    Transit,
+}
+
+impl TravelMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Walk => "walk",
+            Self::Bicycle => "bicycle",
+            Self::Car => "car",
+            Self::Transit => "transit",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Walk => "пешком",
+            Self::Bicycle => "вело",
+            Self::Car => "авто",
+            Self::Transit => "транспорт",
+        }
+    }
+
+    fn osrm_profile(&self) -> Result<&'static str, SSError> {
+        match self {
+            Self::Walk => Ok("foot"),
+            Self::Bicycle => Ok("bike"),
+            Self::Car => Ok("car"),
+            Self::Transit => Err(SSError::Config("OSRM has no transit routing".into())),
+        }
+    }
+
+    fn valhalla_costing(&self) -> &'static str {
+        match self {
+            Self::Walk => "pedestrian",
+            Self::Bicycle => "bicycle",
+            Self::Car => "auto",
+            Self::Transit => "multimodal",
+        }
+    }
+}
+
+/// Place the commute is estimated to
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Destination {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub mode: TravelMode,
+    /// Engine of this destination when it is not `commute.url`, an OSRM
+    /// instance serves one profile
+    pub url: Option<String>,
+    /// Apartments further away are not sent, unknown times pass
+    pub max_minutes: Option<u32>,
+}
+
+/// Travel time from an apartment to a destination
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Commute {
+    pub destination: String,
+    pub mode: TravelMode,
+    pub seconds: i64,
+    pub meters: Option<i64>,
+}
+
+impl Commute {
+    pub fn minutes(&self) -> i64 {
+        (self.seconds + 59) / 60
+    }
+
+    /// "работа 23 мин (вело)"
+    pub fn brief(&self) -> String {
+        format!(
+            "{} {} мин ({})",
+            self.destination,
+            self.minutes(),
+            self.mode.label()
+        )
+    }
+}
+
+/// Route summary, seconds and meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Route {
+    pub seconds: f64,
+    pub meters: Option<f64>,
+}
+
+/// Client of the routing engine, the routes are cached in the database
+pub struct Router {
+    engine: Engine,
+    url: String,
+    destinations: Vec<Destination>,
+    client: reqwest::Client,
+    cache_days: i64,
+    departure: NaiveTime,
+}
+
+impl Router {
+    /// None unless an engine and some destinations are configured
+    pub fn from_config() -> Result<Option<Self>, SSError> {
+        let settings = &Config::settings().commute;
+        let Some(engine) = settings.engine else {
+            return Ok(None);
+        };
+        if settings.destinations.is_empty() {
+            log::warn!("Commute engine is set, but there are no destinations");
+            return Ok(None);
+        }
+        for d in settings.destinations.iter() {
+            if engine == Engine::Osrm {
+                d.mode.osrm_profile()?;
+            }
+            if d.url.as_deref().unwrap_or(&settings.url).is_empty() {
+                return Err(SSError::Config(format!(
+                    "commute: no url of the engine for '{}'",
+                    d.name
+                )));
+            }
+        }
+        let departure = NaiveTime::parse_from_str(&settings.departure, "%H:%M").map_err(|e| {
+            SSError::Config(format!("commute departure '{}': {}", settings.departure, e))
+        })?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .build()?;
+        Ok(Some(Self {
+            engine,
+            url: settings.url.clone(),
+            destinations: settings.destinations.clone(),
+            client,
+            cache_days: settings.cache_days,
+            departure,
+        }))
+    }
+
+    /// Url of the engine the destination is routed by
+    fn url<'a>(&'a self, to: &'a Destination) -> &'a str {
+        to.url.as_deref().unwrap_or(&self.url).trim_end_matches('/')
+    }
+
+    /// Commutes to every destination, the failed routes are left out
+    pub async fn commutes(&self, from: &Location) -> Vec<Commute> {
+        let mut commutes = vec![];
+        for destination in self.destinations.iter() {
+            match self.route_cached(from, destination).await {
+                Ok(route) => commutes.push(Commute {
+                    destination: destination.name.clone(),
+                    mode: destination.mode,
+                    seconds: route.seconds.round() as i64,
+                    meters: route.meters.map(|m| m.round() as i64),
+                }),
+                Err(e) => log::error!(
+                    "Fail to route from {},{} to '{}': {}",
+                    from.latitude,
+                    from.longitude,
+                    destination.name,
+                    e
+                ),
+            }
+        }
+        commutes
+    }
+
+    async fn route_cached(&self, from: &Location, to: &Destination) -> Result<Route, SSError> {
+        let mut record = CommuteRecord::key(self.engine, self.url(to), from, to);
+        match record.select(self.cache_days) {
+            Ok(Some(route)) => return Ok(route),
+            Ok(None) => {}
+            Err(e) => log::error!("Fail to look up the commute cache: {}", e),
+        }
+        let route = self.route(from, to).await?;
+        record.seconds.value = route.seconds;
+        record.meters.value = route.meters;
+        if let Err(e) = record.insert() {
+            log::error!("Fail to save the commute: {}", e);
+        }
+        Ok(route)
+    }
+
+    async fn route(&self, from: &Location, to: &Destination) -> Result<Route, SSError> {
+        let url = self.url(to);
+        let body: Value = match self.engine {
+            Engine::Osrm => {
+                let url = format!(
+                    "{}/route/v1/{}/{},{};{},{}?overview=false",
+                    url,
+                    to.mode.osrm_profile()?,
+                    from.longitude,
+                    from.latitude,
+                    to.longitude,
+                    to.latitude
+                );
+                self.client.get(url).send().await?.json().await?
+            }
+            Engine::Valhalla => {
+                let mut request = json!({
+                    "locations": [
+                        {"lat": from.latitude, "lon": from.longitude},
+                        {"lat": to.latitude, "lon": to.longitude},
+                    ],
+                    "costing": to.mode.valhalla_costing(),
+                    "units": "kilometers",
+                });
+                // Without a time the timetable of the moment is used, a
+                // night ad would get the night trips
+                if to.mode == TravelMode::Transit {
+                    let today = chrono::Local::now().date_naive();
+                    request["date_time"] = json!({
+                        "type": 1,
+                        "value": departure(today, self.departure),
+                    });
+                }
+                let url = format!("{}/route", url);
+                self.client
+                    .post(url)
+                    .json(&request)
+                    .send()
+                    .await?
+                    .json()
+                    .await?
+            }
+        };
+        match self.engine {
+            Engine::Osrm => parse_osrm(&body),
+            Engine::Valhalla => parse_valhalla(&body),
+        }
+    }
+}
+
+/// Local "%Y-%m-%dT%H:%M" of the next working day after `today`, the
+/// departure of the transit routes
+pub fn departure(today: NaiveDate, time: NaiveTime) -> String {
+    let mut day = today + chrono::Duration::days(1);
+    while matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+        day += chrono::Duration::days(1);
+    }
+    day.and_time(time).format("%Y-%m-%dT%H:%M").to_string()
+}
+
+/// `{"code": "Ok", "routes": [{"duration": s, "distance": m}]}`
+pub fn parse_osrm(body: &Value) -> Result<Route, SSError> {
+    if body["code"] != "Ok" {
+        return Err(SSError::Request(format!(
+            "OSRM: {} {}",
+            body["code"].as_str().unwrap_or("no code"),
+            body["message"].as_str().unwrap_or_default()
+        )));
+    }
+    let route = &body["routes"][0];
+    Ok(Route {
+        seconds: route["duration"]
+            .as_f64()
+            .ok_or(SSError::Request("OSRM: no route duration".into()))?,
+        meters: route["distance"].as_f64(),
+    })
+}
+
+/// `{"trip": {"summary": {"time": s, "length": km}}}` or an error message
+pub fn parse_valhalla(body: &Value) -> Result<Route, SSError> {
+    let summary = &body["trip"]["summary"];
+    let Some(seconds) = summary["time"].as_f64() else {
+        return Err(SSError::Request(format!(
+            "Valhalla: {}",
+            body["error"].as_str().unwrap_or("no route")
+        )));
+    };
+    Ok(Route {
+        seconds,
+        meters: summary["length"].as_f64().map(|km| km * 1000.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_routes() {
+        let osrm = json!({"code": "Ok", "routes": [{"duration": 905.3, "distance": 3120.5}]});
+        assert_eq!(
+            parse_osrm(&osrm).unwrap(),
+            Route {
+                seconds: 905.3,
+                meters: Some(3120.5)
+            }
+        );
+        let no_route = json!({"code": "NoRoute", "message": "Impossible route"});
+        assert!(parse_osrm(&no_route).is_err());
+
+        let valhalla = json!({"trip": {"summary": {"time": 1260.0, "length": 4.2}}});
+        assert_eq!(parse_valhalla(&valhalla).unwrap().meters, Some(4200.0));
+        let error = json!({"error_code": 442, "error": "No path could be found"});
+        assert!(parse_valhalla(&error).is_err());
+    }
+
+    #[test]
+    fn departs_on_working_days() {
+        let time = NaiveTime::from_hms_opt(8, 30, 0).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        // Monday, Friday and Saturday
+        assert_eq!(departure(day(19), time), "2026-10-20T08:30");
+        assert_eq!(departure(day(23), time), "2026-10-26T08:30");
+        assert_eq!(departure(day(24), time), "2026-10-26T08:30");
+    }
+
+    #[test]
+    fn rounds_minutes_up() {
+        let commute = Commute {
+            destination: "работа".into(),
+            mode: TravelMode::Bicycle,
+            seconds: 1321,
+            meters: None,
+        };
+        assert_eq!(commute.brief(), "работа 23 мин (вело)");
+    }
+}
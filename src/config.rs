@@ -1,17 +1,463 @@
-use crate::db::utils::DatabaseSource;
+use std::{collections::BTreeMap, sync::OnceLock, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    apartment::LocationSource,
+    area::LocationPolicy,
+    commute::{Destination, Engine},
+    db::utils::DatabaseSource,
+    error::SSError,
+};
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+const DEFAULT_LOCATION: &str = "rentbot_sslv.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    pub url: String,
+    pub price_low: u32,
+    pub price_high: u32,
+    pub area_low: u32,
+    /// Named polygons of the default profile, see `FilterSettings::areas_file`
+    pub areas: Vec<String>,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            url: "https://www.ss.lv/ru/real-estate/flats/riga/today-2/hand_over/filter/".into(),
+            price_low: 300,
+            price_high: 1200,
+            area_low: 70,
+            areas: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub allow_ground_floor: bool,
+    pub allow_top_floor_without_elevator: bool,
+    pub allow_agency: bool,
+    pub allow_commission: bool,
+    /// Only the apartments cheaper per m² than the similar listings are
+    /// sent: 25 is the cheapest quarter of them; unknown scores pass
+    pub max_deal_percentile: Option<f64>,
+    /// GeoJSON of the named polygons the profiles can pick besides the
+    /// shipped Riga ones
+    pub areas_file: String,
+    /// Apartments without the coordinates, or with the district ones only,
+    /// when the profile has areas
+    pub area_without_location: LocationPolicy,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            allow_ground_floor: true,
+            allow_top_floor_without_elevator: true,
+            allow_agency: true,
+            allow_commission: true,
+            max_deal_percentile: None,
+            areas_file: String::new(),
+            area_without_location: LocationPolicy::Pass,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthSettings {
+    pub window: usize,
+    pub min_samples: usize,
+    pub drop_threshold: f64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            window: 6,
+            min_samples: 5,
+            drop_threshold: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    pub retries: u32,
+    pub backoff_base_ms: u64,
+    pub backoff_max_ms: u64,
+    pub requests_per_second: f64,
+    pub circuit_failures: u32,
+    pub circuit_cooldown_secs: u64,
+    pub concurrency: usize,
+    pub politeness_delay_ms: u64,
+    pub proxies: Vec<String>,
+    pub user_agents: Vec<String>,
+    pub accept_language: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            retries: 3,
+            backoff_base_ms: 500,
+            backoff_max_ms: 30_000,
+            requests_per_second: 2.0,
+            circuit_failures: 5,
+            circuit_cooldown_secs: 300,
+            concurrency: 4,
+            politeness_delay_ms: 1000,
+            proxies: vec![],
+            user_agents: [
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+                "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+            ]
+            .map(String::from)
+            .to_vec(),
+            accept_language: "ru-RU,ru;q=0.9,lv;q=0.8,en-US;q=0.7,en;q=0.6".into(),
+            headers: BTreeMap::from([(
+                "Accept".into(),
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8".into(),
+            )]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CycleSettings {
+    pub interval_secs: u64,
+    pub retry_secs: u64,
+    pub revalidate_hours: u64,
+    pub stall_secs: u64,
+}
+
+impl Default for CycleSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60 * 10,
+            retry_secs: 60,
+            revalidate_hours: 24,
+            stall_secs: 60 * 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebSettings {
+    pub bind: String,
+    pub public_url: String,
+    pub feed_days: u32,
+    pub feed_limit: usize,
+    pub api_token: String,
+}
+
+impl Default for WebSettings {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8080".into(),
+            public_url: String::new(),
+            feed_days: 7,
+            feed_limit: 100,
+            api_token: String::new(),
+        }
+    }
+}
+
+/// Travel times of a routing engine, off unless the engine is set
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CommuteSettings {
+    pub engine: Option<Engine>,
+    pub url: String,
+    pub timeout_secs: u64,
+    /// Cached routes older than this are computed again
+    pub cache_days: i64,
+    /// Local "%H:%M" of the next working day the transit routes depart at
+    pub departure: String,
+    pub destinations: Vec<Destination>,
+}
+
+impl Default for CommuteSettings {
+    fn default() -> Self {
+        Self {
+            engine: None,
+            url: String::new(),
+            timeout_secs: 10,
+            cache_days: 30,
+            departure: "08:30".into(),
+            destinations: vec![],
+        }
+    }
+}
+
+/// Coordinates of the ads without the map link, off unless some sources
+/// are set
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GeocoderSettings {
+    /// Tried in order, "nominatim" and "dataset"
+    pub sources: Vec<LocationSource>,
+    /// Self-hosted Nominatim
+    pub url: String,
+    /// CSV of the addresses: street,house,latitude,longitude
+    pub dataset: String,
+    pub timeout_secs: u64,
+    /// Addresses not found are looked up again after this many days
+    pub retry_days: i64,
+}
+
+impl Default for GeocoderSettings {
+    fn default() -> Self {
+        Self {
+            sources: vec![],
+            url: String::new(),
+            dataset: String::new(),
+            timeout_secs: 10,
+            retry_days: 7,
+        }
+    }
+}
+
+/// Content of rentbot_sslv.toml, every value is optional
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub database: String,
+    pub selectors: String,
+    pub fetch_contacts: bool,
+    pub fast_mode: bool,
+    pub search: SearchSettings,
+    pub filter: FilterSettings,
+    pub health: HealthSettings,
+    pub http: HttpSettings,
+    pub cycle: CycleSettings,
+    pub web: WebSettings,
+    pub commute: CommuteSettings,
+    pub geocoder: GeocoderSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            database: "rentbot_sslv.db".into(),
+            selectors: "selectors.toml".into(),
+            fetch_contacts: true,
+            fast_mode: false,
+            search: SearchSettings::default(),
+            filter: FilterSettings::default(),
+            health: HealthSettings::default(),
+            http: HttpSettings::default(),
+            cycle: CycleSettings::default(),
+            web: WebSettings::default(),
+            commute: CommuteSettings::default(),
+            geocoder: GeocoderSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, the defaults are used when there is no file
+    pub fn load(path: &str) -> Result<Self, SSError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                log::info!("Using settings from '{}'", path);
+                toml::from_str(&text).map_err(|e| SSError::Config(format!("{}: {}", path, e)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No '{}', using default settings", path);
+                Ok(Self::default())
+            }
+            Err(e) => Err(SSError::Config(format!("{}: {}", path, e))),
+        }
+    }
+}
 
 pub struct Config {}
 impl Config {
+    /// Settings file location, can be changed with RENTBOT_CONFIG
+    pub fn location() -> String {
+        std::env::var("RENTBOT_CONFIG").unwrap_or(DEFAULT_LOCATION.into())
+    }
+    /// Loads the settings file, must be called before the first use of the
+    /// settings, otherwise the defaults are used
+    pub fn init() -> Result<(), SSError> {
+        let settings = Settings::load(&Self::location())?;
+        Self::set(settings)
+    }
+    pub fn set(settings: Settings) -> Result<(), SSError> {
+        SETTINGS
+            .set(settings)
+            .map_err(|_| SSError::Config("settings are already in use".into()))
+    }
+    pub fn settings() -> &'static Settings {
+        SETTINGS.get_or_init(Settings::default)
+    }
     pub fn database_location() -> DatabaseSource {
-        DatabaseSource::File("rentbot_sslv.db".into())
+        DatabaseSource::File(Self::settings().database.clone())
+    }
+    pub fn selectors_location() -> String {
+        Self::settings().selectors.clone()
+    }
+    /// Filter page of the default search profile
+    pub fn search_url() -> String {
+        Self::settings().search.url.clone()
     }
     pub fn price_low() -> u32 {
-        300
+        Self::settings().search.price_low
     }
     pub fn price_high() -> u32 {
-        1200
+        Self::settings().search.price_high
     }
     pub fn area_low() -> u32 {
-        70
+        Self::settings().search.area_low
+    }
+    pub fn allow_ground_floor() -> bool {
+        Self::settings().filter.allow_ground_floor
+    }
+    pub fn allow_top_floor_without_elevator() -> bool {
+        Self::settings().filter.allow_top_floor_without_elevator
+    }
+    pub fn allow_agency() -> bool {
+        Self::settings().filter.allow_agency
+    }
+    pub fn allow_commission() -> bool {
+        Self::settings().filter.allow_commission
+    }
+    pub fn max_deal_percentile() -> Option<f64> {
+        Self::settings().filter.max_deal_percentile
+    }
+    pub fn area_without_location() -> LocationPolicy {
+        Self::settings().filter.area_without_location
+    }
+    pub fn commute_destinations() -> &'static [Destination] {
+        &Self::settings().commute.destinations
+    }
+    pub fn fetch_contacts() -> bool {
+        Self::settings().fetch_contacts
+    }
+    /// New listings are notified from the search results right away, the
+    /// details follow as a reply once the ad page is fetched
+    pub fn fast_mode() -> bool {
+        Self::settings().fast_mode
+    }
+    /// Number of previous cycles the field success rates are compared with
+    pub fn health_window() -> usize {
+        Self::settings().health.window
+    }
+    /// Fields seen fewer times per cycle are not taken into account
+    pub fn health_min_samples() -> usize {
+        Self::settings().health.min_samples
+    }
+    /// Drop of the success rate (0..1) which triggers an alert
+    pub fn health_drop_threshold() -> f64 {
+        Self::settings().health.drop_threshold
+    }
+    pub fn request_timeout() -> Duration {
+        Duration::from_secs(Self::settings().http.timeout_secs)
+    }
+    pub fn connect_timeout() -> Duration {
+        Duration::from_secs(Self::settings().http.connect_timeout_secs)
+    }
+    /// Number of retries after the first failed attempt
+    pub fn request_retries() -> u32 {
+        Self::settings().http.retries
+    }
+    pub fn backoff_base() -> Duration {
+        Duration::from_millis(Self::settings().http.backoff_base_ms)
+    }
+    pub fn backoff_max() -> Duration {
+        Duration::from_millis(Self::settings().http.backoff_max_ms)
+    }
+    /// Limit of the requests towards ss.lv shared by all the tasks
+    pub fn requests_per_second() -> f64 {
+        Self::settings().http.requests_per_second
+    }
+    /// Consecutive failures which open the circuit
+    pub fn circuit_failures() -> u32 {
+        Self::settings().http.circuit_failures
+    }
+    pub fn circuit_cooldown() -> Duration {
+        Duration::from_secs(Self::settings().http.circuit_cooldown_secs)
+    }
+    /// Number of ad pages fetched at once
+    pub fn concurrency() -> usize {
+        Self::settings().http.concurrency.max(1)
+    }
+    /// Minimal pause between two requests to the same host
+    pub fn politeness_delay() -> Duration {
+        Duration::from_millis(Self::settings().http.politeness_delay_ms)
+    }
+    /// HTTP or SOCKS proxies the requests are rotated over, direct when empty
+    pub fn proxies() -> &'static [String] {
+        &Self::settings().http.proxies
+    }
+    /// Every request picks its User-Agent out of the pool
+    pub fn user_agents() -> &'static [String] {
+        &Self::settings().http.user_agents
+    }
+    pub fn accept_language() -> &'static str {
+        &Self::settings().http.accept_language
+    }
+    /// Headers sent with every request
+    pub fn default_headers() -> &'static BTreeMap<String, String> {
+        &Self::settings().http.headers
+    }
+    pub fn cycle_interval() -> Duration {
+        Duration::from_secs(Self::settings().cycle.interval_secs)
+    }
+    /// Known ad pages are not fetched again until this time passes, zero
+    /// makes every page fetched every cycle
+    pub fn revalidate_after() -> Duration {
+        Duration::from_secs(Self::settings().cycle.revalidate_hours * 60 * 60)
+    }
+    /// Delay before the next attempt of a failed cycle
+    pub fn cycle_retry_interval() -> Duration {
+        Duration::from_secs(Self::settings().cycle.retry_secs)
+    }
+    /// The main loop is stalled if it makes no progress for this long
+    pub fn stall_timeout() -> Duration {
+        Duration::from_secs(Self::settings().cycle.stall_secs)
+    }
+    /// Address of the embedded web server, empty disables it
+    pub fn web_bind() -> &'static str {
+        &Self::settings().web.bind
+    }
+    /// Address the web server is reachable at, used in the feed links;
+    /// the bind address by default
+    pub fn public_url() -> String {
+        match Self::settings().web.public_url.trim_end_matches('/') {
+            "" => format!("http://{}", Self::web_bind()),
+            url => url.to_string(),
+        }
+    }
+    /// Feeds hold the records of the last days only
+    pub fn feed_days() -> u32 {
+        Self::settings().web.feed_days
+    }
+    pub fn feed_limit() -> usize {
+        Self::settings().web.feed_limit
+    }
+    /// Bearer token of the JSON API, RENTBOT_API_TOKEN overrides the
+    /// settings; empty disables the API
+    pub fn api_token() -> String {
+        std::env::var("RENTBOT_API_TOKEN")
+            .ok()
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| Self::settings().web.api_token.clone())
     }
 }
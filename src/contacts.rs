@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use regex::Regex;
+use scraper::Html;
+
+use crate::{error::SSError, fetch::Fetcher, selectors::SelectorSpec};
+
+// The phone numbers of an ad are not part of the page. The page refers to the
+// '#contacts_js' script, which carries entries like
+//   1|JTg5JTlD...eGU=|85730698	2|JTg3JTk2...bw==|82688600
+// Each entry is decoded twice: first with the transformed numeric key of the
+// entry (key*6-47289+517) and then with the key embedded into the ad page.
+const KEY_MUL: u64 = 6;
+const KEY_SUB: u64 = 47289;
+const KEY_ADD: u64 = 517;
+const MIN_PHONE_DIGITS: usize = 6;
+
+/// Transforms the numeric key of the script entry into the 1st phase key,
+/// None when the key is too large
+pub fn transform_key(key: u64) -> Option<u64> {
+    Some(
+        key.checked_mul(KEY_MUL)?
+            .checked_add(KEY_ADD)?
+            .saturating_sub(KEY_SUB),
+    )
+}
+
+/// Decodes base64 encoded, url escaped data shifted by the bytes of the key
+pub fn decode(data: &str, key: &str) -> Result<String, SSError> {
+    let base = base64::engine::general_purpose::STANDARD.decode(data.as_bytes())?;
+    let data_url = std::str::from_utf8(base.as_slice())?;
+    let data_bytes = urlencoding::decode_binary(data_url.as_bytes()).into_owned();
+    let key_bytes = key.as_bytes();
+    if key_bytes.is_empty() {
+        return Err(SSError::Empty);
+    }
+    let mut plain = String::new();
+    for (i, dbyte) in data_bytes.iter().enumerate() {
+        let kbyte = key_bytes[i % key_bytes.len()];
+        let rbyte = (*dbyte as i32 - kbyte as i32 + 14) as u32;
+        plain.push(
+            std::char::from_u32(rbyte)
+                .ok_or(SSError::Decode(format!("invalid char code {}", rbyte)))?,
+        );
+    }
+    Ok(plain)
+}
+
+/// Single entry of the contacts script
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactEntry {
+    pub index: u32,
+    pub data: String,
+    pub key: u64,
+}
+
+impl ContactEntry {
+    pub fn decode(&self, page_key: &str) -> Result<String, SSError> {
+        let key = transform_key(self.key)
+            .ok_or(SSError::Decode(format!("key {} is too large", self.key)))?;
+        let phase1 = decode(&self.data, &key.to_string())?;
+        decode(&phase1, page_key)
+    }
+}
+
+pub fn parse_entries(script: &str) -> Vec<ContactEntry> {
+    let Ok(re) = Regex::new(r#"(\d+)\|([A-Za-z0-9+/=]+)\|(\d+)"#) else {
+        return vec![];
+    };
+    re.captures_iter(script)
+        .filter_map(|c| {
+            Some(ContactEntry {
+                index: c.get(1)?.as_str().parse().ok()?,
+                data: c.get(2)?.as_str().to_string(),
+                key: c.get(3)?.as_str().parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn looks_like_phone(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit() || "+-() ".contains(c))
+        && s.chars().filter(|c| c.is_ascii_digit()).count() >= MIN_PHONE_DIGITS
+}
+
+/// Decodes every entry of the script with the first page key candidate that
+/// gives a phone-like result. Entries nothing fits to are skipped.
+pub fn decode_phones(script: &str, page_keys: &[String]) -> Vec<String> {
+    parse_entries(script)
+        .iter()
+        .filter_map(|e| {
+            page_keys
+                .iter()
+                .filter_map(|k| e.decode(k).ok())
+                .find(|p| looks_like_phone(p))
+        })
+        .collect()
+}
+
+/// Location of the contacts script of an ad and the key candidates of its page
+#[derive(Debug, Clone)]
+pub struct ContactsRequest {
+    pub url: reqwest::Url,
+    pub referer: String,
+    pub page_keys: Vec<String>,
+}
+
+impl ContactsRequest {
+    /// The page key is not labelled anyhow, so every string literal of the
+    /// inline scripts is a candidate
+    pub fn from_page(page: &Html, page_url: &str, script_src: &str) -> Result<Self, SSError> {
+        let url = reqwest::Url::parse(page_url)?.join(script_src)?;
+
+        let literal_re = Regex::new(r#"["']([A-Za-z0-9_\-]{8,32})["']"#)?;
+        let inline_selector = SelectorSpec::global().contacts.inline_scripts_selector()?;
+        let mut page_keys: Vec<String> = vec![];
+        for script in page.select(inline_selector) {
+            let text = script.text().collect::<String>();
+            for c in literal_re.captures_iter(&text) {
+                let key = c[1].to_string();
+                if !page_keys.contains(&key) {
+                    page_keys.push(key);
+                }
+            }
+        }
+        Ok(Self {
+            url,
+            referer: page_url.to_string(),
+            page_keys,
+        })
+    }
+
+    pub async fn request(&self, fetcher: Arc<Fetcher>) -> Result<Vec<String>, SSError> {
+        let script = fetcher
+            .text(|client| {
+                client
+                    .get(self.url.clone())
+                    .header(reqwest::header::REFERER, self.referer.as_str())
+            })
+            .await?;
+        let phones = decode_phones(&script, &self.page_keys);
+        log::debug!("Decoded {} phone(s) out of {}", phones.len(), self.url);
+        Ok(phones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "1|JTg5JTlDJTdCVnYlOTIlN0JXdSVBMHhacyU4RSU3RVpwJTdCJTdEZXMlOEV6JTVFdXolOTMlOTNyeiU5MCU5MXh6eGU=|85730698\t2|JTg3JTk2WCU4RXlqbyU5MHlqJTdEWWwlN0UlOTFabiU3RSU5Mm8=|82688600";
+    const PAGE_KEY: &str = "K0dbVwzGrpLa-wRs2";
+
+    #[test]
+    fn transforms_key() {
+        assert_eq!(transform_key(85730698), Some(514337416));
+        assert_eq!(transform_key(u64::MAX), None);
+        assert_eq!(transform_key(u64::MAX / KEY_MUL), None);
+    }
+
+    #[test]
+    fn decodes_phases() {
+        let phase1 = decode(
+            "JThCJUEyd1R0JThFJTdEJTVDdiVBMndXcSU3RCU4RiU5NHolN0RnJThGcnklOTQlOThzJTdEJThEVG15JTkzJTVD",
+            "77011366",
+        )
+        .unwrap();
+        assert_eq!(phase1, "byU1QiU4MyU4NXglQTElOTlpJTk1JTk4");
+        assert_eq!(decode(&phase1, PAGE_KEY).unwrap(), "29-108-016");
+    }
+
+    #[test]
+    fn parses_entries() {
+        let entries = parse_entries(SCRIPT);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].index, 1);
+        assert_eq!(entries[0].key, 85730698);
+        assert_eq!(entries[1].index, 2);
+        assert_eq!(entries[1].key, 82688600);
+    }
+
+    #[test]
+    fn decodes_phones_with_key_candidates() {
+        let keys = vec!["someOtherLiteral".to_string(), PAGE_KEY.to_string()];
+        assert_eq!(decode_phones(SCRIPT, &keys), vec!["29-299-053".to_string()]);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(decode("not base64!", PAGE_KEY).is_err());
+        assert!(decode_phones(SCRIPT, &[]).is_empty());
+        let huge_key = format!("1|JTg5JTlD|{}", u64::MAX);
+        assert!(decode_phones(&huge_key, &[PAGE_KEY.to_string()]).is_empty());
+    }
+}
@@ -1,2 +1,11 @@
+pub mod commute;
+pub mod cycle;
+pub mod geocode;
+pub mod listing;
+pub mod market;
+pub mod price;
+pub mod profile;
 pub mod record;
+pub mod stats;
+pub mod status;
 pub mod utils;
@@ -0,0 +1,192 @@
+use crate::{
+    apartment::Location,
+    commute::{Destination, Engine, Route},
+    config::Config,
+    error::SSError,
+};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "commute_route";
+/// Table of the older versions, keyed without the url of the engine
+const OLD_TABLE_NAME: &str = "commute";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Origins closer than ~10 m share the route
+fn round(coordinate: f64) -> f64 {
+    (coordinate * 10_000.0).round() / 10_000.0
+}
+
+/// Cached route of the routing engine
+pub struct CommuteRecord {
+    pub engine: Header<String>,
+    /// Instance of the engine, an OSRM instance serves one profile
+    pub url: Header<String>,
+    pub mode: Header<String>,
+    pub from_latitude: Header<f64>,
+    pub from_longitude: Header<f64>,
+    pub to_latitude: Header<f64>,
+    pub to_longitude: Header<f64>,
+    pub seconds: Header<f64>,
+    pub meters: Header<Option<f64>>,
+    /// UTC, "%Y-%m-%d %H:%M:%S"
+    pub computed: Header<String>,
+}
+
+impl Default for CommuteRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommuteRecord {
+    pub fn new() -> Self {
+        Self {
+            engine: Header::new(String::new(), "engine"),
+            url: Header::new(String::new(), "url"),
+            mode: Header::new(String::new(), "mode"),
+            from_latitude: Header::new(0.0, "from_latitude"),
+            from_longitude: Header::new(0.0, "from_longitude"),
+            to_latitude: Header::new(0.0, "to_latitude"),
+            to_longitude: Header::new(0.0, "to_longitude"),
+            seconds: Header::new(0.0, "seconds"),
+            meters: Header::new(None, "meters"),
+            computed: Header::new(String::new(), "computed"),
+        }
+    }
+
+    /// Record of the route, the origin is rounded
+    pub fn key(engine: Engine, url: &str, from: &Location, to: &Destination) -> Self {
+        let mut c = Self::new();
+        c.engine.value = engine.as_str().to_string();
+        c.url.value = url.to_string();
+        c.mode.value = to.mode.as_str().to_string();
+        c.from_latitude.value = round(from.latitude);
+        c.from_longitude.value = round(from.longitude);
+        c.to_latitude.value = to.latitude;
+        c.to_longitude.value = to.longitude;
+        c
+    }
+
+    fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let c = Self::new();
+        // The routes are only a cache, the old ones are computed again
+        conn.execute(&format!("DROP TABLE IF EXISTS {}", OLD_TABLE_NAME), ())?;
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {0} (
+            {1}  TEXT NOT NULL,
+            {2}  TEXT NOT NULL,
+            {3}  TEXT NOT NULL,
+            {4}  REAL NOT NULL,
+            {5}  REAL NOT NULL,
+            {6}  REAL NOT NULL,
+            {7}  REAL NOT NULL,
+            {8}  REAL NOT NULL,
+            {9}  REAL,
+            {10} TEXT NOT NULL,
+            PRIMARY KEY ({1}, {2}, {3}, {4}, {5}, {6}, {7})
+            )",
+            TABLE_NAME,
+            c.engine.name,
+            c.url.name,
+            c.mode.name,
+            c.from_latitude.name,
+            c.from_longitude.name,
+            c.to_latitude.name,
+            c.to_longitude.name,
+            c.seconds.name,
+            c.meters.name,
+            c.computed.name,
+        ));
+        conn.execute(&query, ())?;
+        Ok(())
+    }
+
+    /// Cached route of the key, None when it is older than `cache_days`
+    pub fn select(&self, cache_days: i64) -> Result<Option<Route>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "SELECT {}, {}, {} FROM {} WHERE {}=? AND {}=? AND {}=? AND {}=? AND {}=? AND {}=? AND {}=?",
+            self.seconds.name,
+            self.meters.name,
+            self.computed.name,
+            TABLE_NAME,
+            self.engine.name,
+            self.url.name,
+            self.mode.name,
+            self.from_latitude.name,
+            self.from_longitude.name,
+            self.to_latitude.name,
+            self.to_longitude.name,
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut route_iter = stmt.query_map(
+            (
+                &self.engine.value,
+                &self.url.value,
+                &self.mode.value,
+                self.from_latitude.value,
+                self.from_longitude.value,
+                self.to_latitude.value,
+                self.to_longitude.value,
+            ),
+            |row| {
+                Ok((
+                    Route {
+                        seconds: row.get(0)?,
+                        meters: row.get(1)?,
+                    },
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )?;
+        let Some((route, computed)) = route_iter.next().transpose()? else {
+            return Ok(None);
+        };
+        let expired = chrono::NaiveDateTime::parse_from_str(&computed, DATETIME_FORMAT).map_or(
+            true,
+            |computed| {
+                chrono::Utc::now().naive_utc() - computed > chrono::Duration::days(cache_days)
+            },
+        );
+        Ok(if expired { None } else { Some(route) })
+    }
+
+    pub fn insert(&self) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "INSERT OR REPLACE INTO {} ({}, {}, {}, {}, {}, {}, {}, {}, {}, {})
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            TABLE_NAME,
+            self.engine.name,
+            self.url.name,
+            self.mode.name,
+            self.from_latitude.name,
+            self.from_longitude.name,
+            self.to_latitude.name,
+            self.to_longitude.name,
+            self.seconds.name,
+            self.meters.name,
+            self.computed.name,
+        ));
+        let computed = chrono::Utc::now().format(DATETIME_FORMAT).to_string();
+        conn.execute(
+            &query,
+            (
+                &self.engine.value,
+                &self.url.value,
+                &self.mode.value,
+                self.from_latitude.value,
+                self.from_longitude.value,
+                self.to_latitude.value,
+                self.to_longitude.value,
+                self.seconds.value,
+                self.meters.value,
+                computed,
+            ),
+        )?;
+        Ok(())
+    }
+}
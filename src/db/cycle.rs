@@ -0,0 +1,142 @@
+use crate::{config::Config, error::SSError};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "cycle";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Outcome of a scraping cycle, for the health page
+pub struct CycleRecord {
+    /// Local time, "%Y-%m-%d %H:%M:%S"
+    pub started: Header<String>,
+    pub finished: Header<String>,
+    pub profiles: Header<i64>,
+    /// Listings on the search pages
+    pub found: Header<i64>,
+    /// Ad pages requested
+    pub fetched: Header<i64>,
+    /// Ad pages parsed
+    pub pages: Header<i64>,
+    pub failed: Header<i64>,
+    /// Parser health of the cycle
+    pub report: Header<String>,
+    pub alerts: Header<String>,
+}
+
+impl Default for CycleRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CycleRecord {
+    pub fn new() -> Self {
+        Self {
+            started: Header::new(String::new(), "started"),
+            finished: Header::new(String::new(), "finished"),
+            profiles: Header::new(0, "profiles"),
+            found: Header::new(0, "found"),
+            fetched: Header::new(0, "fetched"),
+            pages: Header::new(0, "pages"),
+            failed: Header::new(0, "failed"),
+            report: Header::new(String::new(), "report"),
+            alerts: Header::new(String::new(), "alerts"),
+        }
+    }
+
+    pub fn now() -> String {
+        chrono::Local::now().format(DATETIME_FORMAT).to_string()
+    }
+
+    fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let c = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL
+            )",
+            TABLE_NAME,
+            c.started.name,
+            c.finished.name,
+            c.profiles.name,
+            c.found.name,
+            c.fetched.name,
+            c.pages.name,
+            c.failed.name,
+            c.report.name,
+            c.alerts.name,
+        ));
+        conn.execute(&query, ())?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let mut c = CycleRecord::new();
+        c.started.value = row.get(c.started.name)?;
+        c.finished.value = row.get(c.finished.name)?;
+        c.profiles.value = row.get(c.profiles.name)?;
+        c.found.value = row.get(c.found.name)?;
+        c.fetched.value = row.get(c.fetched.name)?;
+        c.pages.value = row.get(c.pages.name)?;
+        c.failed.value = row.get(c.failed.name)?;
+        c.report.value = row.get(c.report.name)?;
+        c.alerts.value = row.get(c.alerts.name)?;
+        Ok(c)
+    }
+
+    /// The latest cycles, newest first
+    pub fn select_last(limit: usize) -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} ORDER BY rowid DESC LIMIT ?",
+            TABLE_NAME
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let cycles = stmt
+            .query_map([limit as i64], Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(cycles)
+    }
+
+    pub fn insert(&self) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "INSERT INTO {} ({}, {}, {}, {}, {}, {}, {}, {}, {})
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            TABLE_NAME,
+            self.started.name,
+            self.finished.name,
+            self.profiles.name,
+            self.found.name,
+            self.fetched.name,
+            self.pages.name,
+            self.failed.name,
+            self.report.name,
+            self.alerts.name,
+        ));
+        conn.execute(
+            &query,
+            (
+                &self.started.value,
+                &self.finished.value,
+                self.profiles.value,
+                self.found.value,
+                self.fetched.value,
+                self.pages.value,
+                self.failed.value,
+                &self.report.value,
+                &self.alerts.value,
+            ),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    apartment::{Location, LocationSource, Precision},
+    config::Config,
+    error::SSError,
+};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "geocode";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Cached answer of the geocoder, the addresses not found are cached as well
+pub struct GeocodeRecord {
+    pub source: Header<String>,
+    /// Normalised "city|district|address"
+    pub query: Header<String>,
+    pub latitude: Header<Option<f64>>,
+    pub longitude: Header<Option<f64>>,
+    pub precision: Header<Option<String>>,
+    /// UTC, "%Y-%m-%d %H:%M:%S"
+    pub resolved: Header<String>,
+}
+
+impl Default for GeocodeRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeocodeRecord {
+    pub fn new() -> Self {
+        Self {
+            source: Header::new(String::new(), "source"),
+            query: Header::new(String::new(), "query"),
+            latitude: Header::new(None, "latitude"),
+            longitude: Header::new(None, "longitude"),
+            precision: Header::new(None, "precision"),
+            resolved: Header::new(String::new(), "resolved"),
+        }
+    }
+
+    pub fn key(source: LocationSource, query: &str) -> Self {
+        let mut g = Self::new();
+        g.source.value = source.as_str().to_string();
+        g.query.value = query.to_string();
+        g
+    }
+
+    fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let g = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {0} (
+            {1}  TEXT NOT NULL,
+            {2}  TEXT NOT NULL,
+            {3}  REAL,
+            {4}  REAL,
+            {5}  TEXT,
+            {6}  TEXT NOT NULL,
+            PRIMARY KEY ({1}, {2})
+            )",
+            TABLE_NAME,
+            g.source.name,
+            g.query.name,
+            g.latitude.name,
+            g.longitude.name,
+            g.precision.name,
+            g.resolved.name,
+        ));
+        conn.execute(&query, ())?;
+        Ok(())
+    }
+
+    /// None if the key is not cached or the miss is older than `retry_days`,
+    /// Some(None) for a recent miss
+    pub fn select(&self, retry_days: i64) -> Result<Option<Option<Location>>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "SELECT {}, {}, {}, {} FROM {} WHERE {}=? AND {}=?",
+            self.latitude.name,
+            self.longitude.name,
+            self.precision.name,
+            self.resolved.name,
+            TABLE_NAME,
+            self.source.name,
+            self.query.name,
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut rows = stmt.query_map((&self.source.value, &self.query.value), |row| {
+            Ok((
+                row.get::<_, Option<f64>>(0)?,
+                row.get::<_, Option<f64>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        let Some((latitude, longitude, precision, resolved)) = rows.next().transpose()? else {
+            return Ok(None);
+        };
+        let source = LocationSource::parse(&self.source.value).unwrap_or_default();
+        match (latitude, longitude) {
+            (Some(latitude), Some(longitude)) => Ok(Some(Some(Location {
+                latitude,
+                longitude,
+                source,
+                precision: precision
+                    .as_deref()
+                    .and_then(Precision::parse)
+                    .unwrap_or(Precision::City),
+            }))),
+            _ => {
+                let retry = chrono::NaiveDateTime::parse_from_str(&resolved, DATETIME_FORMAT)
+                    .map_or(true, |resolved| {
+                        chrono::Utc::now().naive_utc() - resolved
+                            > chrono::Duration::days(retry_days)
+                    });
+                Ok(if retry { None } else { Some(None) })
+            }
+        }
+    }
+
+    /// Saves the answer, None when the address is not found
+    pub fn insert(&mut self, location: Option<&Location>) -> Result<(), SSError> {
+        self.latitude.value = location.map(|l| l.latitude);
+        self.longitude.value = location.map(|l| l.longitude);
+        self.precision.value = location.map(|l| l.precision.as_str().to_string());
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "INSERT OR REPLACE INTO {} ({}, {}, {}, {}, {}, {})
+            VALUES (?, ?, ?, ?, ?, ?)",
+            TABLE_NAME,
+            self.source.name,
+            self.query.name,
+            self.latitude.name,
+            self.longitude.name,
+            self.precision.name,
+            self.resolved.name,
+        ));
+        let resolved = chrono::Utc::now().format(DATETIME_FORMAT).to_string();
+        conn.execute(
+            &query,
+            (
+                &self.source.value,
+                &self.query.value,
+                self.latitude.value,
+                self.longitude.value,
+                &self.precision.value,
+                resolved,
+            ),
+        )?;
+        Ok(())
+    }
+}
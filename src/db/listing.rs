@@ -0,0 +1,295 @@
+use crate::{apartment::Apartment, config::Config, error::SSError, fetch::Validators};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "listing";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Every ad page fetched so far, sent or not, with the time of the last fetch,
+/// its cache validators and what the market analytics need of it
+pub struct ListingRecord {
+    pub id: Header<String>,
+    pub url: Header<String>,
+    /// UTC, "%Y-%m-%d %H:%M:%S"
+    pub first_seen: Header<String>,
+    pub last_fetched: Header<String>,
+    pub etag: Header<Option<String>>,
+    pub last_modified: Header<Option<String>>,
+    /// Last time the listing was on a search page, none before it was tracked
+    pub last_seen: Header<Option<String>>,
+    pub price_eur: Header<Option<i64>>,
+    pub area: Header<Option<f64>>,
+    pub rooms: Header<Option<i64>>,
+    pub district: Header<Option<String>>,
+    pub floor: Header<Option<i64>>,
+    pub total_floors: Header<Option<i64>>,
+    /// Search profiles the page was fetched for, comma separated; none
+    /// before they were tracked
+    pub profiles: Header<Option<String>>,
+}
+
+impl Default for ListingRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ListingRecord {
+    pub fn new() -> Self {
+        Self {
+            id: Header::new(String::new(), "id"),
+            url: Header::new(String::new(), "url"),
+            first_seen: Header::new(String::new(), "first_seen"),
+            last_fetched: Header::new(String::new(), "last_fetched"),
+            etag: Header::new(None, "etag"),
+            last_modified: Header::new(None, "last_modified"),
+            last_seen: Header::new(None, "last_seen"),
+            price_eur: Header::new(None, "price_eur"),
+            area: Header::new(None, "area"),
+            rooms: Header::new(None, "rooms"),
+            district: Header::new(None, "district"),
+            floor: Header::new(None, "floor"),
+            total_floors: Header::new(None, "total_floors"),
+            profiles: Header::new(None, "profiles"),
+        }
+    }
+
+    /// Columns added after the first version of the table, with their types
+    fn extra_columns(&self) -> [(&'static str, &'static str); 8] {
+        [
+            (self.last_seen.name, "TEXT"),
+            (self.price_eur.name, "INTEGER"),
+            (self.area.name, "REAL"),
+            (self.rooms.name, "INTEGER"),
+            (self.district.name, "TEXT"),
+            (self.floor.name, "INTEGER"),
+            (self.total_floors.name, "INTEGER"),
+            (self.profiles.name, "TEXT"),
+        ]
+    }
+
+    pub fn now() -> String {
+        chrono::Utc::now().format(DATETIME_FORMAT).to_string()
+    }
+
+    pub(crate) fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let l = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            {}  TEXT PRIMARY KEY,
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL,
+            {}  TEXT,
+            {}  TEXT
+            )",
+            TABLE_NAME,
+            l.id.name,
+            l.url.name,
+            l.first_seen.name,
+            l.last_fetched.name,
+            l.etag.name,
+            l.last_modified.name,
+        ));
+        conn.execute(&query, ())?;
+
+        // Tables of the older versions lack the later columns
+        utils::add_missing_columns(conn, TABLE_NAME, &l.extra_columns())?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let mut l = ListingRecord::new();
+        l.id.value = row.get(l.id.name)?;
+        l.url.value = row.get(l.url.name)?;
+        l.first_seen.value = row.get(l.first_seen.name)?;
+        l.last_fetched.value = row.get(l.last_fetched.name)?;
+        l.etag.value = row.get(l.etag.name)?;
+        l.last_modified.value = row.get(l.last_modified.name)?;
+        l.last_seen.value = row.get(l.last_seen.name)?;
+        l.price_eur.value = row.get(l.price_eur.name)?;
+        l.area.value = row.get(l.area.name)?;
+        l.rooms.value = row.get(l.rooms.name)?;
+        l.district.value = row.get(l.district.name)?;
+        l.floor.value = row.get(l.floor.name)?;
+        l.total_floors.value = row.get(l.total_floors.name)?;
+        l.profiles.value = row.get(l.profiles.name)?;
+        Ok(l)
+    }
+
+    pub fn select_by_id(id: &str) -> Result<Option<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let l = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} WHERE {}=?",
+            TABLE_NAME, l.id.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut record_iter = stmt.query_map([id], Self::from_row)?;
+        Ok(record_iter.next().transpose()?)
+    }
+
+    /// Saves the fetch, the first_seen of the known listing is kept and its
+    /// profiles are added to
+    pub fn upsert(&self) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let known = {
+            let query = query_wrapper(format!(
+                "SELECT * FROM {} WHERE {}=?",
+                TABLE_NAME, self.id.name
+            ));
+            let mut stmt = conn.prepare(&query)?;
+            let mut record_iter = stmt.query_map([&self.id.value], Self::from_row)?;
+            record_iter.next().transpose()?
+        };
+        let mut profiles: Vec<&str> = known
+            .as_ref()
+            .map_or(vec![], |k| k.profile_names().collect());
+        for name in self.profile_names() {
+            if !profiles.contains(&name) {
+                profiles.push(name);
+            }
+        }
+        let profiles = Some(profiles.join(",")).filter(|p| !p.is_empty());
+        let query = query_wrapper(format!(
+            "INSERT INTO {0} ({1}, {2}, {3}, {4}, {5}, {6}, {7}, {8}, {9}, {10}, {11}, {12}, {13}, {14})
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT({1}) DO UPDATE SET
+            {2}=excluded.{2}, {4}=excluded.{4}, {5}=excluded.{5}, {6}=excluded.{6},
+            {7}=excluded.{7}, {8}=excluded.{8}, {9}=excluded.{9}, {10}=excluded.{10},
+            {11}=excluded.{11}, {12}=excluded.{12}, {13}=excluded.{13}, {14}=excluded.{14}",
+            TABLE_NAME,
+            self.id.name,
+            self.url.name,
+            self.first_seen.name,
+            self.last_fetched.name,
+            self.etag.name,
+            self.last_modified.name,
+            self.last_seen.name,
+            self.price_eur.name,
+            self.area.name,
+            self.rooms.name,
+            self.district.name,
+            self.floor.name,
+            self.total_floors.name,
+            self.profiles.name,
+        ));
+        conn.execute(
+            &query,
+            (
+                &self.id.value,
+                &self.url.value,
+                &self.first_seen.value,
+                &self.last_fetched.value,
+                &self.etag.value,
+                &self.last_modified.value,
+                &self.last_seen.value,
+                &self.price_eur.value,
+                &self.area.value,
+                &self.rooms.value,
+                &self.district.value,
+                &self.floor.value,
+                &self.total_floors.value,
+                &profiles,
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Records the fetch of the page which is not modified since the last one
+    pub fn touch(id: &str) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let l = Self::new();
+        let query = query_wrapper(format!(
+            "UPDATE {} SET {}=?, {}=? WHERE {}=?",
+            TABLE_NAME, l.last_fetched.name, l.last_seen.name, l.id.name
+        ));
+        let now = Self::now();
+        conn.execute(&query, (&now, &now, id))?;
+        Ok(())
+    }
+
+    /// Records the listing is on a search page, the page is not fetched
+    pub fn seen(id: &str) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let l = Self::new();
+        let query = query_wrapper(format!(
+            "UPDATE {} SET {}=? WHERE {}=?",
+            TABLE_NAME, l.last_seen.name, l.id.name
+        ));
+        conn.execute(&query, (Self::now(), id))?;
+        Ok(())
+    }
+
+    /// First and last time every listing was seen, the last fetch stands for
+    /// the listings seen before it was tracked
+    pub fn lifetimes() -> Result<Vec<(String, String)>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let l = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT {}, COALESCE({}, {}) FROM {}",
+            l.first_seen.name, l.last_seen.name, l.last_fetched.name, TABLE_NAME
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let lifetimes = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lifetimes)
+    }
+
+    pub fn fetched(apartment: &Apartment, validators: &Validators, profiles: &[String]) -> Self {
+        let now = Self::now();
+        let mut l = Self::new();
+        l.id.value = apartment.id.clone();
+        l.url.value = apartment.url.clone();
+        l.price_eur.value = apartment.price_eur().map(i64::from);
+        l.area.value = Some(apartment.area);
+        l.rooms.value = Some(apartment.rooms as i64);
+        l.district.value = Some(apartment.district.clone()).filter(|d| !d.is_empty());
+        l.floor.value = apartment.floor;
+        l.total_floors.value = apartment.total_floors;
+        l.first_seen.value = now.clone();
+        l.last_fetched.value = now.clone();
+        l.last_seen.value = Some(now);
+        l.etag.value = validators.etag.clone();
+        l.last_modified.value = validators.last_modified.clone();
+        l.profiles.value = Some(profiles.join(",")).filter(|p| !p.is_empty());
+        l
+    }
+
+    fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles
+            .value
+            .iter()
+            .flat_map(|p| p.split(','))
+            .filter(|p| !p.is_empty())
+    }
+
+    /// Every one of the profiles has got the page, the listings fetched
+    /// before the profiles were tracked count for all of them
+    pub fn fetched_for(&self, profiles: &[String]) -> bool {
+        self.profiles.value.is_none()
+            || profiles
+                .iter()
+                .all(|p| self.profile_names().any(|known| known == p))
+    }
+
+    pub fn validators(&self) -> Validators {
+        Validators {
+            etag: self.etag.value.clone(),
+            last_modified: self.last_modified.value.clone(),
+        }
+    }
+
+    /// Time passed since the last fetch
+    pub fn age(&self) -> Option<chrono::Duration> {
+        chrono::NaiveDateTime::parse_from_str(&self.last_fetched.value, DATETIME_FORMAT)
+            .ok()
+            .map(|t| chrono::Utc::now().naive_utc() - t)
+    }
+}
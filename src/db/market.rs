@@ -0,0 +1,116 @@
+use crate::{apartment::Apartment, config::Config, error::SSError};
+
+use super::{
+    listing::{self, ListingRecord},
+    record::{ApartmentRecrod, TABLE_NAME},
+    utils::{self, query_wrapper},
+};
+
+/// Apartment as the analytics see it: the latest record of a sent one or
+/// the last fetch of the others
+#[derive(Debug, Clone, Default)]
+pub struct MarketSample {
+    pub id: String,
+    /// Date of the ad or the first fetch of the page, "%Y-%m-%d %H:%M:%S"
+    pub first_sent: String,
+    pub price_eur: Option<i64>,
+    pub area: Option<f64>,
+    pub rooms: Option<i64>,
+    pub district: Option<String>,
+    pub floor: Option<i64>,
+    pub total_floors: Option<i64>,
+}
+
+impl From<&Apartment> for MarketSample {
+    fn from(a: &Apartment) -> Self {
+        Self {
+            id: a.id.clone(),
+            first_sent: a.datetime.to_string(),
+            price_eur: a.price_eur().map(i64::from),
+            area: Some(a.area),
+            rooms: Some(a.rooms as i64),
+            district: Some(a.district.clone()).filter(|d| !d.is_empty()),
+            floor: a.floor,
+            total_floors: a.total_floors,
+        }
+    }
+}
+
+impl MarketSample {
+    /// Every fetched apartment, or the ones sent for the profile
+    pub fn select(profile: Option<&str>) -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        ApartmentRecrod::create_table(&conn)?;
+        ListingRecord::create_table(&conn)?;
+        let r = ApartmentRecrod::new();
+        let l = ListingRecord::new();
+        let (latest, params) = match profile {
+            Some(profile) => (
+                format!(
+                    "SELECT MAX(rowid) FROM {} WHERE {}=? GROUP BY {}",
+                    TABLE_NAME, r.profile.name, r.id.name
+                ),
+                vec![profile.to_string()],
+            ),
+            None => (
+                format!(
+                    "SELECT MAX(rowid) FROM {} GROUP BY {}",
+                    TABLE_NAME, r.id.name
+                ),
+                vec![],
+            ),
+        };
+        // The pages which are not sent are of no profile
+        let unsent = match profile {
+            Some(_) => String::new(),
+            None => format!(
+                "UNION ALL SELECT l.{id}, l.{first_seen}, l.{price_eur}, l.{area}, l.{rooms},
+                l.{district}, l.{floor}, l.{total_floors}
+                FROM {listing} l WHERE l.{area} IS NOT NULL
+                AND l.{id} NOT IN (SELECT {id} FROM {table})",
+                listing = listing::TABLE_NAME,
+                table = TABLE_NAME,
+                id = l.id.name,
+                first_seen = l.first_seen.name,
+                price_eur = l.price_eur.name,
+                area = l.area.name,
+                rooms = l.rooms.name,
+                district = l.district.name,
+                floor = l.floor.name,
+                total_floors = l.total_floors.name,
+            ),
+        };
+        let query = query_wrapper(format!(
+            "SELECT r.{id}, (SELECT MIN(f.{datetime}) FROM {table} f WHERE f.{id}=r.{id}),
+            r.{price_eur}, r.{area}, r.{rooms}, r.{district}, r.{floor}, r.{total_floors}
+            FROM {table} r WHERE r.rowid IN ({latest}) {unsent}",
+            table = TABLE_NAME,
+            id = r.id.name,
+            datetime = r.datetime.name,
+            price_eur = r.price_eur.name,
+            area = r.area.name,
+            rooms = r.rooms.name,
+            district = r.district.name,
+            floor = r.floor.name,
+            total_floors = r.total_floors.name,
+            latest = latest,
+            unsent = unsent,
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let samples = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok(Self {
+                    id: row.get(0)?,
+                    first_sent: row.get(1)?,
+                    price_eur: row.get(2)?,
+                    area: row.get(3)?,
+                    rooms: row.get(4)?,
+                    district: row.get(5)?,
+                    floor: row.get(6)?,
+                    total_floors: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(samples)
+    }
+}
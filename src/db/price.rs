@@ -0,0 +1,96 @@
+use crate::{config::Config, error::SSError};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "price";
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Price of a listing since the given time, a row is added on every change
+pub struct PriceRecord {
+    pub id: Header<String>,
+    /// As shown on the page, "600 €/мес."
+    pub price: Header<String>,
+    pub price_eur: Header<Option<i64>>,
+    /// UTC, "%Y-%m-%d %H:%M:%S"
+    pub seen: Header<String>,
+}
+
+impl Default for PriceRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceRecord {
+    pub fn new() -> Self {
+        Self {
+            id: Header::new(String::new(), "id"),
+            price: Header::new(String::new(), "price"),
+            price_eur: Header::new(None, "price_eur"),
+            seen: Header::new(String::new(), "seen"),
+        }
+    }
+
+    fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL,
+            {}  INTEGER,
+            {}  TEXT NOT NULL
+            )",
+            TABLE_NAME, p.id.name, p.price.name, p.price_eur.name, p.seen.name,
+        ));
+        conn.execute(&query, ())?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let mut p = PriceRecord::new();
+        p.id.value = row.get(p.id.name)?;
+        p.price.value = row.get(p.price.name)?;
+        p.price_eur.value = row.get(p.price_eur.name)?;
+        p.seen.value = row.get(p.seen.name)?;
+        Ok(p)
+    }
+
+    /// Price changes of the listing, oldest first
+    pub fn select_by_id(id: &str) -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} WHERE {}=? ORDER BY rowid",
+            TABLE_NAME, p.id.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let prices = stmt
+            .query_map([id], Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(prices)
+    }
+
+    /// Saves the price unless it is the last known one, true if saved
+    pub fn record(id: &str, price: &str, price_eur: Option<i64>) -> Result<bool, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT {} FROM {} WHERE {}=? ORDER BY rowid DESC LIMIT 1",
+            p.price.name, TABLE_NAME, p.id.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut last_iter = stmt.query_map([id], |row| row.get::<_, String>(0))?;
+        if last_iter.next().transpose()?.as_deref() == Some(price) {
+            return Ok(false);
+        }
+        let query = query_wrapper(format!(
+            "INSERT INTO {} ({}, {}, {}, {}) VALUES (?, ?, ?, ?)",
+            TABLE_NAME, p.id.name, p.price.name, p.price_eur.name, p.seen.name,
+        ));
+        let seen = chrono::Utc::now().format(DATETIME_FORMAT).to_string();
+        conn.execute(&query, (id, price, price_eur, seen))?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    config::Config,
+    error::SSError,
+    page_handler::{SearchPageBuilder, SearchPageRequest},
+};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "profile";
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Search on ss.lv the bot runs every cycle, each has its own feed
+pub struct SearchProfile {
+    /// Unique, used in the urls
+    pub name: Header<String>,
+    pub url: Header<String>,
+    pub price_low: Header<u32>,
+    pub price_high: Header<u32>,
+    pub area_low: Header<u32>,
+    pub enabled: Header<bool>,
+    /// Names of the shipped or `filter.areas_file` polygons, comma separated
+    pub areas: Header<Option<String>>,
+    /// GeoJSON of the own polygons
+    pub polygons: Header<Option<String>>,
+}
+
+impl Default for SearchProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchProfile {
+    pub fn new() -> Self {
+        Self {
+            name: Header::new(String::new(), "name"),
+            url: Header::new(String::new(), "url"),
+            price_low: Header::new(0, "price_low"),
+            price_high: Header::new(0, "price_high"),
+            area_low: Header::new(0, "area_low"),
+            enabled: Header::new(true, "enabled"),
+            areas: Header::new(None, "areas"),
+            polygons: Header::new(None, "polygons"),
+        }
+    }
+
+    /// Columns added after the first version of the table, with their types
+    fn extra_columns(&self) -> [(&'static str, &'static str); 2] {
+        [(self.areas.name, "TEXT"), (self.polygons.name, "TEXT")]
+    }
+
+    pub fn area_names(&self) -> Vec<String> {
+        self.areas
+            .value
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// The search of the settings file
+    pub fn from_config() -> Self {
+        let mut p = Self::new();
+        p.name.value = DEFAULT_PROFILE.to_string();
+        p.url.value = Config::search_url();
+        p.price_low.value = Config::price_low();
+        p.price_high.value = Config::price_high();
+        p.area_low.value = Config::area_low();
+        p.areas.value = Some(Config::settings().search.areas.join(",")).filter(|a| !a.is_empty());
+        p
+    }
+
+    pub fn request(&self) -> Result<SearchPageRequest, SSError> {
+        SearchPageBuilder::new()
+            .url(&self.url.value)
+            .min_area(self.area_low.value)
+            .max_price(self.price_high.value)
+            .min_price(self.price_low.value)
+            .build()
+    }
+
+    fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            {}  TEXT PRIMARY KEY,
+            {}  TEXT NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL,
+            {}  INTEGER NOT NULL
+            )",
+            TABLE_NAME,
+            p.name.name,
+            p.url.name,
+            p.price_low.name,
+            p.price_high.name,
+            p.area_low.name,
+            p.enabled.name,
+        ));
+        conn.execute(&query, ())?;
+
+        // Tables of the older versions lack the later columns
+        utils::add_missing_columns(conn, TABLE_NAME, &p.extra_columns())?;
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let mut p = SearchProfile::new();
+        p.name.value = row.get(p.name.name)?;
+        p.url.value = row.get(p.url.name)?;
+        p.price_low.value = row.get(p.price_low.name)?;
+        p.price_high.value = row.get(p.price_high.name)?;
+        p.area_low.value = row.get(p.area_low.name)?;
+        p.enabled.value = row.get(p.enabled.name)?;
+        p.areas.value = row.get(p.areas.name)?;
+        p.polygons.value = row.get(p.polygons.name)?;
+        Ok(p)
+    }
+
+    pub fn select_all() -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} ORDER BY {}",
+            TABLE_NAME, p.name.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let profiles = stmt
+            .query_map([], Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(profiles)
+    }
+
+    pub fn select_by_name(name: &str) -> Result<Option<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} WHERE {}=?",
+            TABLE_NAME, p.name.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut profile_iter = stmt.query_map([name], Self::from_row)?;
+        Ok(profile_iter.next().transpose()?)
+    }
+
+    /// Enabled profiles, the default one is created out of the settings when
+    /// there are no profiles at all
+    pub fn select_enabled() -> Result<Vec<Self>, SSError> {
+        let mut profiles = Self::select_all()?;
+        if profiles.is_empty() {
+            let profile = Self::from_config();
+            log::info!("Creating the '{}' search profile", profile.name.value);
+            profile.insert()?;
+            profiles.push(profile);
+        }
+        profiles.retain(|p| p.enabled.value);
+        Ok(profiles)
+    }
+
+    /// Replaces the search of the profile with the same name, false if
+    /// there is no such profile
+    pub fn update(&self) -> Result<bool, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "UPDATE {} SET {}=?, {}=?, {}=?, {}=?, {}=?, {}=?, {}=? WHERE {}=?",
+            TABLE_NAME,
+            self.url.name,
+            self.price_low.name,
+            self.price_high.name,
+            self.area_low.name,
+            self.enabled.name,
+            self.areas.name,
+            self.polygons.name,
+            self.name.name,
+        ));
+        let updated = conn.execute(
+            &query,
+            (
+                &self.url.value,
+                self.price_low.value,
+                self.price_high.value,
+                self.area_low.value,
+                self.enabled.value,
+                &self.areas.value,
+                &self.polygons.value,
+                &self.name.value,
+            ),
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// False if there is no such profile, its records are kept
+    pub fn delete(name: &str) -> Result<bool, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let p = Self::new();
+        let query = query_wrapper(format!(
+            "DELETE FROM {} WHERE {}=?",
+            TABLE_NAME, p.name.name
+        ));
+        Ok(conn.execute(&query, [name])? > 0)
+    }
+
+    pub fn insert(&self) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let query = query_wrapper(format!(
+            "INSERT INTO {} ({}, {}, {}, {}, {}, {}, {}, {})
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            TABLE_NAME,
+            self.name.name,
+            self.url.name,
+            self.price_low.name,
+            self.price_high.name,
+            self.area_low.name,
+            self.enabled.name,
+            self.areas.name,
+            self.polygons.name,
+        ));
+        conn.execute(
+            &query,
+            (
+                &self.name.value,
+                &self.url.value,
+                self.price_low.value,
+                self.price_high.value,
+                self.area_low.value,
+                self.enabled.value,
+                &self.areas.value,
+                &self.polygons.value,
+            ),
+        )?;
+        Ok(())
+    }
+}
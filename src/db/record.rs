@@ -1,17 +1,247 @@
 use core::fmt;
+use std::collections::HashMap;
 
-use crate::config::Config;
+use crate::{config::Config, error::SSError};
 
-use super::utils::{self, query_wrapper, Header};
+use super::{
+    status::{self, ListingStatus, StatusRecord},
+    utils::{self, query_wrapper, Header},
+};
 
-const TABLE_NAME: &str = "record";
+pub(crate) const TABLE_NAME: &str = "record";
 
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Datetime,
+    Price,
+    Area,
+    Distance,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 4] = [
+        SortKey::Datetime,
+        SortKey::Price,
+        SortKey::Area,
+        SortKey::Distance,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Datetime => "datetime",
+            SortKey::Price => "price",
+            SortKey::Area => "area",
+            SortKey::Distance => "distance",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            SortKey::Datetime => "datetime",
+            SortKey::Price => "price_eur",
+            SortKey::Area => "area",
+            SortKey::Distance => "distance",
+        }
+    }
+}
+
+/// Filters, order and page of the stored apartments, each apartment is
+/// listed once even if several profiles have found it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordQuery {
+    pub profile: Option<String>,
+    pub price_min: Option<i64>,
+    pub price_max: Option<i64>,
+    pub area_min: Option<f64>,
+    pub area_max: Option<f64>,
+    /// Part of the district name, case sensitive for non-ASCII
+    pub district: Option<String>,
+    pub distance_max: Option<i64>,
+    pub status: Option<ListingStatus>,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl RecordQuery {
+    /// Out of the url query, empty values are ignored
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, SSError> {
+        fn get<T: std::str::FromStr>(
+            params: &HashMap<String, String>,
+            name: &str,
+        ) -> Result<Option<T>, SSError> {
+            match params.get(name).map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                Some(v) => v.parse().map(Some).map_err(|_| SSError::field(name, v)),
+                None => Ok(None),
+            }
+        }
+        let sort = match get::<String>(params, "sort")? {
+            Some(sort) => SortKey::ALL
+                .into_iter()
+                .find(|k| k.as_str() == sort)
+                .ok_or(SSError::field("sort", sort))?,
+            None => SortKey::default(),
+        };
+        let descending = match get::<String>(params, "order")?.as_deref() {
+            None | Some("desc") => true,
+            Some("asc") => false,
+            Some(order) => return Err(SSError::field("order", order)),
+        };
+        Ok(Self {
+            profile: get(params, "profile")?,
+            price_min: get(params, "price_min")?,
+            price_max: get(params, "price_max")?,
+            area_min: get(params, "area_min")?,
+            area_max: get(params, "area_max")?,
+            district: get(params, "district")?,
+            distance_max: get(params, "distance_max")?,
+            status: get::<String>(params, "status")?
+                .map(|s| ListingStatus::parse(&s))
+                .transpose()?,
+            sort,
+            descending,
+            limit: get(params, "limit")?,
+            offset: get(params, "offset")?.unwrap_or_default(),
+        })
+    }
+
+    /// WHERE clause and its parameters
+    fn condition(&self) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+        let r = ApartmentRecrod::new();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let latest = match self.profile.as_ref() {
+            Some(profile) => {
+                params.push(Box::new(profile.clone()));
+                format!(
+                    "SELECT MAX(rowid) FROM {} WHERE {}=? GROUP BY {}",
+                    TABLE_NAME, r.profile.name, r.id.name
+                )
+            }
+            None => format!(
+                "SELECT MAX(rowid) FROM {} GROUP BY {}",
+                TABLE_NAME, r.id.name
+            ),
+        };
+        let mut conditions = vec![format!("r.rowid IN ({})", latest)];
+        let mut push = |condition: String, param: Box<dyn rusqlite::ToSql>| {
+            conditions.push(condition);
+            params.push(param);
+        };
+        if let Some(v) = self.price_min {
+            push(format!("r.{} >= ?", r.price_eur.name), Box::new(v));
+        }
+        if let Some(v) = self.price_max {
+            push(format!("r.{} <= ?", r.price_eur.name), Box::new(v));
+        }
+        if let Some(v) = self.area_min {
+            push(format!("r.{} >= ?", r.area.name), Box::new(v));
+        }
+        if let Some(v) = self.area_max {
+            push(format!("r.{} <= ?", r.area.name), Box::new(v));
+        }
+        if let Some(v) = self.district.as_ref() {
+            push(
+                format!("r.{} LIKE ?", r.district.name),
+                Box::new(format!("%{}%", v)),
+            );
+        }
+        if let Some(v) = self.distance_max {
+            push(format!("r.{} <= ?", r.distance.name), Box::new(v));
+        }
+        if let Some(v) = self.status {
+            push(
+                "COALESCE(s.status, 'new') = ?".to_string(),
+                Box::new(v.as_str()),
+            );
+        }
+        (conditions.join(" AND "), params)
+    }
+
+    /// Matching apartments with their statuses
+    pub fn select(&self) -> Result<Vec<(ApartmentRecrod, ListingStatus)>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        ApartmentRecrod::create_table(&conn)?;
+        StatusRecord::create_table(&conn)?;
+        let (condition, mut params) = self.condition();
+        let query = query_wrapper(format!(
+            "SELECT r.*, COALESCE(s.status, 'new') AS triage FROM {} r
+            LEFT JOIN {} s ON s.id = r.id
+            WHERE {}
+            ORDER BY r.{} IS NULL, r.{} {} LIMIT ? OFFSET ?",
+            TABLE_NAME,
+            status::TABLE_NAME,
+            condition,
+            self.sort.column(),
+            self.sort.column(),
+            if self.descending { "DESC" } else { "ASC" },
+        ));
+        params.push(Box::new(self.limit.map_or(-1, |l| l as i64)));
+        params.push(Box::new(self.offset as i64));
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok((
+                    ApartmentRecrod::from_row(row)?,
+                    row.get::<_, String>("triage")?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(record, status)| Ok((record, ListingStatus::parse(&status)?)))
+            .collect()
+    }
+
+    /// Number of the matching apartments regardless of the page
+    pub fn count(&self) -> Result<usize, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        ApartmentRecrod::create_table(&conn)?;
+        StatusRecord::create_table(&conn)?;
+        let (condition, params) = self.condition();
+        let query = query_wrapper(format!(
+            "SELECT COUNT(*) FROM {} r LEFT JOIN {} s ON s.id = r.id WHERE {}",
+            TABLE_NAME,
+            status::TABLE_NAME,
+            condition,
+        ));
+        let count: i64 =
+            conn.query_row(&query, rusqlite::params_from_iter(params.iter()), |row| {
+                row.get(0)
+            })?;
+        Ok(count as usize)
+    }
+}
+
+/// Notified apartment, one row per search profile which has matched it
 pub struct ApartmentRecrod {
     pub id: Header<String>,
     pub datetime: Header<String>,
     pub price: Header<String>,
     pub url: Header<String>,
     pub brief: Header<String>,
+    pub profile: Header<String>,
+    pub price_eur: Header<Option<i64>>,
+    pub rooms: Header<Option<i64>>,
+    pub area: Header<Option<f64>>,
+    pub district: Header<Option<String>>,
+    pub address: Header<Option<String>>,
+    pub floor: Header<Option<i64>>,
+    pub total_floors: Header<Option<i64>>,
+    pub latitude: Header<Option<f64>>,
+    pub longitude: Header<Option<f64>>,
+    /// "map", "nominatim" or "dataset"
+    pub location_source: Header<Option<String>>,
+    /// "exact", "house", "street", "district" or "city"
+    pub location_precision: Header<Option<String>>,
+    pub distance: Header<Option<i64>>,
+    pub description: Header<Option<String>>,
+}
+
+impl Default for ApartmentRecrod {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ApartmentRecrod {
@@ -22,10 +252,45 @@ impl ApartmentRecrod {
             price: Header::new(String::new(), "price"),
             url: Header::new(String::new(), "url"),
             brief: Header::new(String::new(), "brief"),
+            profile: Header::new(String::new(), "profile"),
+            price_eur: Header::new(None, "price_eur"),
+            rooms: Header::new(None, "rooms"),
+            area: Header::new(None, "area"),
+            district: Header::new(None, "district"),
+            address: Header::new(None, "address"),
+            floor: Header::new(None, "floor"),
+            total_floors: Header::new(None, "total_floors"),
+            latitude: Header::new(None, "latitude"),
+            longitude: Header::new(None, "longitude"),
+            location_source: Header::new(None, "location_source"),
+            location_precision: Header::new(None, "location_precision"),
+            distance: Header::new(None, "distance"),
+            description: Header::new(None, "description"),
         }
     }
-    fn create_table(&self) -> Result<(), rusqlite::Error> {
-        let conn = utils::open(Config::database_location())?;
+
+    /// Columns added after the first version of the table, with their types
+    fn extra_columns(&self) -> [(&'static str, &'static str); 14] {
+        [
+            (self.profile.name, "TEXT NOT NULL DEFAULT 'default'"),
+            (self.price_eur.name, "INTEGER"),
+            (self.rooms.name, "INTEGER"),
+            (self.area.name, "REAL"),
+            (self.district.name, "TEXT"),
+            (self.address.name, "TEXT"),
+            (self.floor.name, "INTEGER"),
+            (self.total_floors.name, "INTEGER"),
+            (self.latitude.name, "REAL"),
+            (self.longitude.name, "REAL"),
+            (self.distance.name, "INTEGER"),
+            (self.description.name, "TEXT"),
+            (self.location_source.name, "TEXT"),
+            (self.location_precision.name, "TEXT"),
+        ]
+    }
+
+    pub(crate) fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let r = Self::new();
         let query = query_wrapper(format!(
             // id(text), price(text), url(text), brief(text)
             "CREATE TABLE IF NOT EXISTS {} (
@@ -35,90 +300,167 @@ impl ApartmentRecrod {
             {}  TEXT NOT NULL,
             {}  TEXT NOT NULL
             )",
-            TABLE_NAME,
-            self.datetime.name,
-            self.id.name,
-            self.price.name,
-            self.url.name,
-            self.brief.name,
+            TABLE_NAME, r.datetime.name, r.id.name, r.price.name, r.url.name, r.brief.name,
         ));
-
         conn.execute(&query, ())?;
 
+        // Tables of the older versions lack the structured columns
+        utils::add_missing_columns(conn, TABLE_NAME, &r.extra_columns())?;
         Ok(())
     }
 
-    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+    pub(crate) fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
         let mut a = ApartmentRecrod::new();
-        a.id.value = row.get(0)?;
-        a.price.value = row.get(1)?;
-        a.url.value = row.get(2)?;
-        a.brief.value = row.get(3)?;
+        a.id.value = row.get(a.id.name)?;
+        a.datetime.value = row.get(a.datetime.name)?;
+        a.price.value = row.get(a.price.name)?;
+        a.url.value = row.get(a.url.name)?;
+        a.brief.value = row.get(a.brief.name)?;
+        a.profile.value = row.get(a.profile.name)?;
+        a.price_eur.value = row.get(a.price_eur.name)?;
+        a.rooms.value = row.get(a.rooms.name)?;
+        a.area.value = row.get(a.area.name)?;
+        a.district.value = row.get(a.district.name)?;
+        a.address.value = row.get(a.address.name)?;
+        a.floor.value = row.get(a.floor.name)?;
+        a.total_floors.value = row.get(a.total_floors.name)?;
+        a.latitude.value = row.get(a.latitude.name)?;
+        a.longitude.value = row.get(a.longitude.name)?;
+        a.location_source.value = row.get(a.location_source.name)?;
+        a.location_precision.value = row.get(a.location_precision.name)?;
+        a.distance.value = row.get(a.distance.name)?;
+        a.description.value = row.get(a.description.name)?;
         Ok(a)
     }
 
-    pub fn select_one_by<T: fmt::Display>(h: &Header<T>) -> Result<Self, rusqlite::Error> {
+    pub fn select_one_by<T: fmt::Display>(h: &Header<T>) -> Result<Self, SSError> {
         let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
         let query = query_wrapper(format!(
             "SELECT * FROM {} WHERE {}='{}' ORDER BY rowid DESC LIMIT 1",
             TABLE_NAME, h.name, h.value,
         ));
 
         let mut stmt = conn.prepare(&query)?;
-        let mut record_iter = stmt.query_map([], |row| Self::from_row(row))?;
-        record_iter
+        let mut record_iter = stmt.query_map([], Self::from_row)?;
+        Ok(record_iter
             .next()
-            .unwrap_or_else(|| Err(rusqlite::Error::QueryReturnedNoRows))
+            .unwrap_or(Err(rusqlite::Error::QueryReturnedNoRows))?)
     }
-    pub fn select_one_exp_by<T: fmt::Display>(h: &Header<T>) -> Result<Self, rusqlite::Error> {
+    pub fn select_one_exp_by<T: fmt::Display>(h: &Header<T>) -> Result<Self, SSError> {
         let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
         let query = query_wrapper(format!(
             "SELECT * FROM {} WHERE {}='{}' and datetime>datetime('now', '-7 days') ORDER BY rowid DESC LIMIT 1",
             TABLE_NAME, h.name, h.value,
         ));
 
         let mut stmt = conn.prepare(&query)?;
-        let mut record_iter = stmt.query_map([], |row| Self::from_row(row))?;
-        record_iter
+        let mut record_iter = stmt.query_map([], Self::from_row)?;
+        Ok(record_iter
             .next()
-            .unwrap_or_else(|| Err(rusqlite::Error::QueryReturnedNoRows))
+            .unwrap_or(Err(rusqlite::Error::QueryReturnedNoRows))?)
+    }
+
+    /// The apartment is already recorded for the profile
+    pub fn exists_for_profile(id: &str, profile: &str) -> Result<bool, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let r = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT COUNT(*) FROM {} WHERE {}=? AND {}=?",
+            TABLE_NAME, r.id.name, r.profile.name
+        ));
+        let count: i64 = conn.query_row(&query, [id, profile], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// Every record of the apartment, one per profile, newest first
+    pub fn select_by_id(id: &str) -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let r = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {} WHERE {}=? ORDER BY rowid DESC",
+            TABLE_NAME, r.id.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let records = stmt
+            .query_map([id], Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Latest records of the profile published after `since`
+    /// ("%Y-%m-%d %H:%M:%S"), newest first
+    pub fn select_recent(profile: &str, since: &str, limit: usize) -> Result<Vec<Self>, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let r = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT * FROM {0} WHERE {1}=? AND {2}>=? ORDER BY {2} DESC LIMIT ?",
+            TABLE_NAME, r.profile.name, r.datetime.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let records = stmt
+            .query_map((profile, since, limit as i64), Self::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
     }
-    pub fn insert(&self) -> Result<Self, rusqlite::Error> {
+
+    pub fn insert(&self) -> Result<(), SSError> {
         let conn = utils::open(Config::database_location())?;
-        self.create_table()?;
+        Self::create_table(&conn)?;
         let query = query_wrapper(format!(
-            "INSERT INTO {} ({}, {},{},{},{})
-            VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO {} ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {})
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             TABLE_NAME,
             self.datetime.name,
             self.id.name,
             self.price.name,
             self.url.name,
-            self.brief.name
+            self.brief.name,
+            self.profile.name,
+            self.price_eur.name,
+            self.rooms.name,
+            self.area.name,
+            self.district.name,
+            self.address.name,
+            self.floor.name,
+            self.total_floors.name,
+            self.latitude.name,
+            self.longitude.name,
+            self.distance.name,
+            self.description.name,
+            self.location_source.name,
+            self.location_precision.name,
         ));
         conn.execute(
             &query,
-            (
+            rusqlite::params![
                 &self.datetime.value,
                 &self.id.value,
                 &self.price.value,
                 &self.url.value,
                 &self.brief.value,
-            ),
+                &self.profile.value,
+                &self.price_eur.value,
+                &self.rooms.value,
+                &self.area.value,
+                &self.district.value,
+                &self.address.value,
+                &self.floor.value,
+                &self.total_floors.value,
+                &self.latitude.value,
+                &self.longitude.value,
+                &self.distance.value,
+                &self.description.value,
+                &self.location_source.value,
+                &self.location_precision.value,
+            ],
         )?;
-
-        let query = query_wrapper(format!(
-            "SELECT * FROM {} ORDER BY {} DESC LIMIT 1",
-            TABLE_NAME, self.id.name
-        ));
-
-        let mut stmt = conn.prepare(&query)?;
-        let mut record_iter = stmt.query_map([], |row| Self::from_row(row))?;
-
-        // Ok(User::new())
-        let record = record_iter.next().unwrap();
-        record
+        Ok(())
     }
 
-    // pub fn store(&self) -> Result<(), rusqlite::Error> {}
+    // pub fn store(&self) -> Result<(), SSError> {}
 }
@@ -0,0 +1,109 @@
+use std::fmt::Display;
+
+use crate::{config::Config, error::SSError};
+
+use super::{cycle, listing, price, profile, record, status, utils};
+
+/// Sizes of the tables, the missing tables are empty
+#[derive(Debug, Default)]
+pub struct DbStats {
+    /// Notified apartments, each counted once
+    pub apartments: usize,
+    /// By profile
+    pub records: Vec<(String, usize)>,
+    pub profiles: usize,
+    pub listings: usize,
+    /// By status, the apartments without a status are new
+    pub statuses: Vec<(String, usize)>,
+    pub price_changes: usize,
+    pub cycles: usize,
+    pub last_cycle: Option<String>,
+}
+
+fn exists(conn: &rusqlite::Connection, table: &str) -> Result<bool, SSError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name=?",
+        [table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn count(conn: &rusqlite::Connection, table: &str, what: &str) -> Result<usize, SSError> {
+    if !exists(conn, table)? {
+        return Ok(0);
+    }
+    let count: i64 = conn.query_row(
+        &format!("SELECT COUNT({}) FROM {}", what, table),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+fn group(
+    conn: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+) -> Result<Vec<(String, usize)>, SSError> {
+    if !exists(conn, table)? {
+        return Ok(vec![]);
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {0}, COUNT(*) FROM {1} GROUP BY {0} ORDER BY {0}",
+        column, table
+    ))?;
+    let groups = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(groups)
+}
+
+impl DbStats {
+    pub fn collect() -> Result<Self, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        let last_cycle = if exists(&conn, cycle::TABLE_NAME)? {
+            conn.query_row(
+                &format!("SELECT MAX(finished) FROM {}", cycle::TABLE_NAME),
+                [],
+                |row| row.get(0),
+            )?
+        } else {
+            None
+        };
+        Ok(Self {
+            apartments: count(&conn, record::TABLE_NAME, "DISTINCT id")?,
+            records: group(&conn, record::TABLE_NAME, "profile")?,
+            profiles: count(&conn, profile::TABLE_NAME, "*")?,
+            listings: count(&conn, listing::TABLE_NAME, "*")?,
+            statuses: group(&conn, status::TABLE_NAME, "status")?,
+            price_changes: count(&conn, price::TABLE_NAME, "*")?,
+            cycles: count(&conn, cycle::TABLE_NAME, "*")?,
+            last_cycle,
+        })
+    }
+}
+
+impl Display for DbStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "apartments:    {}", self.apartments)?;
+        for (profile, count) in self.records.iter() {
+            writeln!(f, "  {}: {}", profile, count)?;
+        }
+        writeln!(f, "profiles:      {}", self.profiles)?;
+        writeln!(f, "listings:      {}", self.listings)?;
+        writeln!(f, "statuses:")?;
+        for (status, count) in self.statuses.iter() {
+            writeln!(f, "  {}: {}", status, count)?;
+        }
+        writeln!(f, "price changes: {}", self.price_changes)?;
+        writeln!(f, "cycles:        {}", self.cycles)?;
+        write!(
+            f,
+            "last cycle:    {}",
+            self.last_cycle.as_deref().unwrap_or("-")
+        )
+    }
+}
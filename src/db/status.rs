@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use crate::{config::Config, error::SSError};
+
+use super::utils::{self, query_wrapper, Header};
+
+pub(crate) const TABLE_NAME: &str = "status";
+
+/// Triage state of a listing set by hand
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListingStatus {
+    #[default]
+    New,
+    Interested,
+    Contacted,
+    Rejected,
+}
+
+impl ListingStatus {
+    pub const ALL: [ListingStatus; 4] = [
+        ListingStatus::New,
+        ListingStatus::Interested,
+        ListingStatus::Contacted,
+        ListingStatus::Rejected,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingStatus::New => "new",
+            ListingStatus::Interested => "interested",
+            ListingStatus::Contacted => "contacted",
+            ListingStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, SSError> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or(SSError::Request(format!("unknown status '{}'", s)))
+    }
+}
+
+impl Display for ListingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Status of a listing, the listings without a row are new
+pub struct StatusRecord {
+    pub id: Header<String>,
+    pub status: Header<String>,
+    /// UTC, "%Y-%m-%d %H:%M:%S"
+    pub updated: Header<String>,
+}
+
+impl Default for StatusRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusRecord {
+    pub fn new() -> Self {
+        Self {
+            id: Header::new(String::new(), "id"),
+            status: Header::new(String::new(), "status"),
+            updated: Header::new(String::new(), "updated"),
+        }
+    }
+
+    pub(crate) fn create_table(conn: &rusqlite::Connection) -> Result<(), SSError> {
+        let s = Self::new();
+        let query = query_wrapper(format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+            {}  TEXT PRIMARY KEY,
+            {}  TEXT NOT NULL,
+            {}  TEXT NOT NULL
+            )",
+            TABLE_NAME, s.id.name, s.status.name, s.updated.name,
+        ));
+        conn.execute(&query, ())?;
+        Ok(())
+    }
+
+    pub fn set(id: &str, status: ListingStatus) -> Result<(), SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let s = Self::new();
+        let query = query_wrapper(format!(
+            "INSERT INTO {0} ({1}, {2}, {3}) VALUES (?, ?, datetime('now'))
+            ON CONFLICT({1}) DO UPDATE SET {2}=excluded.{2}, {3}=excluded.{3}",
+            TABLE_NAME, s.id.name, s.status.name, s.updated.name,
+        ));
+        conn.execute(&query, (id, status.as_str()))?;
+        Ok(())
+    }
+
+    pub fn get(id: &str) -> Result<ListingStatus, SSError> {
+        let conn = utils::open(Config::database_location())?;
+        Self::create_table(&conn)?;
+        let s = Self::new();
+        let query = query_wrapper(format!(
+            "SELECT {} FROM {} WHERE {}=?",
+            s.status.name, TABLE_NAME, s.id.name
+        ));
+        let mut stmt = conn.prepare(&query)?;
+        let mut status_iter = stmt.query_map([id], |row| row.get::<_, String>(0))?;
+        match status_iter.next().transpose()? {
+            Some(status) => ListingStatus::parse(&status),
+            None => Ok(ListingStatus::New),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use rusqlite::{Connection, Result};
 
+use crate::metrics::Metrics;
+
 pub enum DatabaseSource {
     Memory,
     File(String),
@@ -8,7 +10,11 @@ pub enum DatabaseSource {
 pub fn open(src: DatabaseSource) -> Result<Connection> {
     match src {
         DatabaseSource::Memory => Ok(Connection::open_in_memory()?),
-        DatabaseSource::File(path) => Ok(Connection::open(path.as_str())?),
+        DatabaseSource::File(path) => {
+            let mut conn = Connection::open(path.as_str())?;
+            conn.profile(Some(Metrics::db_statement));
+            Ok(conn)
+        }
     }
 }
 
@@ -24,10 +30,28 @@ impl<T> Header<T> {
     }
 }
 
+/// Adds the columns the table of an older version lacks
+pub fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let existing = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?;
+    for (name, kind) in columns {
+        if !existing.iter().any(|e| e == name) {
+            let query = query_wrapper(format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, name, kind
+            ));
+            conn.execute(&query, ())?;
+        }
+    }
+    Ok(())
+}
+
 #[inline]
 pub fn query_wrapper(query: String) -> String {
-    let mut query_final = query.replace("\n", " ");
-    while let Some(_) = query_final.find("  ") {
+    let mut query_final = query.replace('\n', " ");
+    while query_final.contains("  ") {
         query_final = query_final.replace("  ", " ");
     }
     log::debug!("#SQL: [{}]", query_final);
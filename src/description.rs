@@ -0,0 +1,560 @@
+use std::fmt::Display;
+
+use crate::apartment::ApartmentDescription;
+
+/// Features looked up in the free text of an ad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Parking,
+    Balcony,
+    Elevator,
+    Pets,
+    Furniture,
+    Dishwasher,
+    Commission,
+    Deposit,
+}
+
+/// Deposit conditions mentioned in the description
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Deposit {
+    /// Deposit is mentioned, but the size is unknown
+    Mentioned,
+    /// Explicitly stated that there is no deposit
+    NoDeposit,
+    Months(u32),
+    Amount(u32),
+}
+
+impl Display for Deposit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Deposit::Mentioned => write!(f, "+"),
+            Deposit::NoDeposit => write!(f, "-"),
+            Deposit::Months(m) => write!(f, "{} мес", m),
+            Deposit::Amount(a) => write!(f, "{} eur", a),
+        }
+    }
+}
+
+// Keywords in ru/lv/en. A word ending with '*' is matched as a prefix, words
+// separated by a space form a phrase. A leading '!' marks a keyword which is
+// negative on its own (e.g. "unfurnished").
+static DICTIONARY: &[(Feature, &[&str])] = &[
+    (
+        Feature::Parking,
+        &[
+            "парковк*",
+            "парковочн*",
+            "паркинг*",
+            "машиномест*",
+            "стоянк*",
+            "гараж*",
+            "stāvviet*",
+            "stavviet*",
+            "autostāvviet*",
+            "autostavviet*",
+            "parkošan*",
+            "parkosan*",
+            "garāž*",
+            "garaz*",
+            "parking*",
+            "garage*",
+            "!no parking",
+            "!no garage*",
+        ],
+    ),
+    (
+        Feature::Balcony,
+        &[
+            "балкон*",
+            "лоджи*",
+            "террас*",
+            "терасс*",
+            "balkon*",
+            "lodžij*",
+            "lodzij*",
+            "teras*",
+            "balcon*",
+            "terrace*",
+            "loggia*",
+            "!no balcon*",
+            "!no terrace*",
+        ],
+    ),
+    (
+        Feature::Elevator,
+        &["лифт*", "lift*", "elevator*", "!no lift", "!no elevator*"],
+    ),
+    (
+        Feature::Pets,
+        &[
+            "животн*",
+            "питом*",
+            "собак*",
+            "кошк*",
+            "кот",
+            "коты",
+            "котом",
+            "mājdzīvniek*",
+            "majdzivniek*",
+            "dzīvniek*",
+            "dzivniek*",
+            "suns",
+            "suņ*",
+            "kaķ*",
+            "pet",
+            "pets",
+            "dog",
+            "dogs",
+            "cat",
+            "cats",
+            "!no pet*",
+            "!no dog*",
+            "!no cat*",
+        ],
+    ),
+    (
+        Feature::Furniture,
+        &[
+            "мебел*",
+            "меблир*",
+            "!немеблир*",
+            "mēbel*",
+            "mebel*",
+            "!nemēbelēt*",
+            "!nemebelet*",
+            "furnish*",
+            "furniture",
+            "!unfurnished",
+            "!no furniture",
+        ],
+    ),
+    (
+        Feature::Dishwasher,
+        &[
+            "посудомо*",
+            "trauku mazgāj*",
+            "trauku mazgaj*",
+            "trauku mašīn*",
+            "dishwasher*",
+            "!no dishwasher*",
+        ],
+    ),
+    (
+        Feature::Commission,
+        &[
+            // "комиссия 0%", "0% komisija"; the '%' is not a token
+            "!комисси* 0",
+            "!0 комисси*",
+            "!komisij* 0",
+            "!0 komisij*",
+            "!commission* 0",
+            "!0 commission*",
+            "!commission free",
+            "комисси*",
+            "komisij*",
+            "!bezkomisij*",
+            "commission*",
+            "!no commission*",
+        ],
+    ),
+    (
+        Feature::Deposit,
+        &[
+            "депозит*",
+            "залог*",
+            "drošības naud*",
+            "drosibas naud*",
+            "depozīt*",
+            "depozit*",
+            "deposit*",
+            "!no deposit*",
+        ],
+    ),
+];
+
+// Negation words placed in front of a keyword: "без балкона", "not furnished".
+// English "no" is not here, it is "from" in Latvian ("5 min no stāvvietas"),
+// the dictionary has the English "no ..." phrases instead
+static NEGATIONS_BEFORE: &[&str] = &[
+    "без",
+    "нет",
+    "не",
+    "нельзя",
+    "запрещ*",
+    "bez",
+    "nav",
+    "ne",
+    "nedrīkst",
+    "aizliegt*",
+    "not",
+    "without",
+    "non",
+];
+
+// Negation words placed after a keyword: "животных нет", "pets not allowed"
+static NEGATIONS_AFTER: &[&str] = &[
+    "нет",
+    "не",
+    "нельзя",
+    "запрещ*",
+    "nav",
+    "ne",
+    "nedrīkst",
+    "aizliegt*",
+    "not",
+    "prohibited",
+    "forbidden",
+];
+
+// Words which end the scope of a negation: "без мебели, но с балконом"
+static NEGATION_BREAKERS: &[&str] = &["с", "со", "но", "ar", "bet", "with", "but"];
+
+static MONTH_UNITS: &[&str] = &["мес*", "mēne*", "mene*", "month*"];
+static CURRENCY_UNITS: &[&str] = &["eur*", "€", "евро", "eiro"];
+
+static NUMBER_WORDS: &[(&str, u32)] = &[
+    ("один", 1),
+    ("одного", 1),
+    ("одному", 1),
+    ("два", 2),
+    ("двух", 2),
+    ("три", 3),
+    ("трех", 3),
+    ("трёх", 3),
+    ("viens", 1),
+    ("viena", 1),
+    ("vienu", 1),
+    ("divi", 2),
+    ("divu", 2),
+    ("trīs", 3),
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+];
+
+const NEGATION_WINDOW_BEFORE: usize = 3;
+const NEGATION_WINDOW_AFTER: usize = 2;
+const DEPOSIT_WINDOW: usize = 5;
+
+/// A single keyword hit inside a clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    pub feature: Feature,
+    pub clause: usize,
+    pub position: usize,
+    pub len: usize,
+    pub negated: bool,
+}
+
+/// Splits the description into clauses of lowercased tokens. Negations never
+/// cross the clause boundary.
+pub fn tokenize(text: &str) -> Vec<Vec<String>> {
+    let mut clauses = vec![];
+    let mut clause = vec![];
+    let mut token = String::new();
+    let chars: Vec<char> = text.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        let between_digits = i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit());
+        if c.is_alphanumeric() || (between_digits && (*c == '.' || *c == ',')) {
+            token.extend(c.to_lowercase());
+            continue;
+        }
+        if !token.is_empty() {
+            clause.push(std::mem::take(&mut token));
+        }
+        match c {
+            '€' => clause.push(c.to_string()),
+            '.' | ',' | ';' | '!' | '?' | '\n' | '(' | ')' if !clause.is_empty() => {
+                clauses.push(std::mem::take(&mut clause));
+            }
+            _ => {}
+        }
+    }
+    if !token.is_empty() {
+        clause.push(token);
+    }
+    if !clause.is_empty() {
+        clauses.push(clause);
+    }
+    clauses
+}
+
+fn word_matches(pattern: &str, token: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => token.starts_with(prefix),
+        None => token == pattern,
+    }
+}
+
+fn any_matches(patterns: &[&str], token: &str) -> bool {
+    patterns.iter().any(|p| word_matches(p, token))
+}
+
+/// Returns the number of tokens matched by the phrase at the position
+fn phrase_matches(phrase: &str, tokens: &[String], position: usize) -> Option<usize> {
+    let words: Vec<&str> = phrase.split(' ').collect();
+    let matched = words
+        .iter()
+        .enumerate()
+        .all(|(i, w)| tokens.get(position + i).is_some_and(|t| word_matches(w, t)));
+    matched.then_some(words.len())
+}
+
+fn negated_before(tokens: &[String], position: usize) -> bool {
+    let from = position.saturating_sub(NEGATION_WINDOW_BEFORE);
+    for t in tokens[from..position].iter().rev() {
+        if any_matches(NEGATION_BREAKERS, t) {
+            return false;
+        }
+        if any_matches(NEGATIONS_BEFORE, t) {
+            return true;
+        }
+    }
+    false
+}
+
+fn negated_after(tokens: &[String], end: usize) -> bool {
+    tokens
+        .iter()
+        .skip(end)
+        .take(NEGATION_WINDOW_AFTER)
+        .take_while(|t| !any_matches(NEGATION_BREAKERS, t))
+        .any(|t| any_matches(NEGATIONS_AFTER, t))
+}
+
+fn parse_number(token: &str) -> Option<u32> {
+    token
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .map(|n| n as u32)
+        .or_else(|| {
+            NUMBER_WORDS
+                .iter()
+                .find(|(w, _)| *w == token)
+                .map(|(_, n)| *n)
+        })
+}
+
+/// Keyword based analyzer of the ad description
+#[derive(Debug, Default)]
+pub struct DescriptionAnalyzer {
+    clauses: Vec<Vec<String>>,
+}
+
+impl DescriptionAnalyzer {
+    pub fn new(text: &str) -> Self {
+        Self {
+            clauses: tokenize(text),
+        }
+    }
+
+    pub fn mentions(&self) -> Vec<Mention> {
+        let mut mentions = vec![];
+        for (ci, tokens) in self.clauses.iter().enumerate() {
+            let mut position = 0;
+            while position < tokens.len() {
+                let found = DICTIONARY.iter().find_map(|(feature, keywords)| {
+                    keywords.iter().find_map(|k| {
+                        let (negative, phrase) = match k.strip_prefix('!') {
+                            Some(p) => (true, p),
+                            None => (false, *k),
+                        };
+                        phrase_matches(phrase, tokens, position)
+                            .map(|len| (*feature, len, negative))
+                    })
+                });
+                match found {
+                    Some((feature, len, negative)) => {
+                        let negated = negative
+                            || negated_before(tokens, position)
+                            || negated_after(tokens, position + len);
+                        mentions.push(Mention {
+                            feature,
+                            clause: ci,
+                            position,
+                            len,
+                            negated,
+                        });
+                        position += len;
+                    }
+                    None => position += 1,
+                }
+            }
+        }
+        mentions
+    }
+
+    /// Some(true) if the feature is mentioned, Some(false) if it is mentioned
+    /// only with a negation and None if it is not mentioned at all
+    pub fn feature(mentions: &[Mention], feature: Feature) -> Option<bool> {
+        let mut result = None;
+        for m in mentions.iter().filter(|m| m.feature == feature) {
+            if !m.negated {
+                return Some(true);
+            }
+            result = Some(false);
+        }
+        result
+    }
+
+    pub fn deposit(&self, mentions: &[Mention]) -> Option<Deposit> {
+        let mention = mentions.iter().find(|m| m.feature == Feature::Deposit)?;
+        if mention.negated {
+            return Some(Deposit::NoDeposit);
+        }
+        let tokens = &self.clauses[mention.clause];
+        let after = mention.position + mention.len;
+        // Look for "депозит 2 месяца" first and "2 месяца депозит" afterwards
+        let candidates = (after..tokens.len().min(after + DEPOSIT_WINDOW))
+            .chain((mention.position.saturating_sub(2)..mention.position).rev());
+        for i in candidates {
+            let Some(number) = parse_number(&tokens[i]) else {
+                continue;
+            };
+            let mut number = Some(number);
+            let mut unit = i + 1;
+            // Thousands may be separated by a space: "1 000"
+            while let Some(t) = tokens.get(unit) {
+                if t.len() == 3 && t.chars().all(|c| c.is_ascii_digit()) {
+                    let group = t.parse::<u32>().unwrap_or_default();
+                    number = number.and_then(|n| n.checked_mul(1000)?.checked_add(group));
+                    unit += 1;
+                } else {
+                    break;
+                }
+            }
+            // Too large to be a deposit, the groups of it are no other number
+            let Some(number) = number else {
+                return Some(Deposit::Mentioned);
+            };
+            return Some(match tokens.get(unit) {
+                Some(u) if any_matches(MONTH_UNITS, u) => Deposit::Months(number),
+                Some(u) if any_matches(CURRENCY_UNITS, u) => Deposit::Amount(number),
+                _ if number <= 12 => Deposit::Months(number),
+                _ => Deposit::Amount(number),
+            });
+        }
+        Some(Deposit::Mentioned)
+    }
+
+    pub fn analyze(&self) -> ApartmentDescription {
+        let mentions = self.mentions();
+        let positive = |f| Self::feature(&mentions, f).unwrap_or(false);
+        ApartmentDescription {
+            park: positive(Feature::Parking),
+            elevator: positive(Feature::Elevator),
+            balkony: positive(Feature::Balcony),
+            pets: Self::feature(&mentions, Feature::Pets),
+            furnished: Self::feature(&mentions, Feature::Furniture),
+            dishwasher: positive(Feature::Dishwasher),
+            commission: Self::feature(&mentions, Feature::Commission),
+            deposit: self.deposit(&mentions),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(text: &str) -> ApartmentDescription {
+        DescriptionAnalyzer::new(text).analyze()
+    }
+
+    #[test]
+    fn finds_features() {
+        let d =
+            analyze("Парковка во дворе, есть балкон и посудомоечная машина. Можно с животными.");
+        assert!(d.park && d.balkony && d.dishwasher);
+        assert_eq!(d.pets, Some(true));
+        assert_eq!(d.furnished, None);
+        assert_eq!(d.deposit, None);
+
+        let d = analyze("Dzīvoklis ar balkonu un mēbelēm, stāvvieta pagalmā");
+        assert!(d.park && d.balkony);
+        assert_eq!(d.furnished, Some(true));
+    }
+
+    #[test]
+    fn handles_negations() {
+        let d = analyze("Без балкона, животных нельзя, немеблированная");
+        assert!(!d.balkony);
+        assert_eq!(d.pets, Some(false));
+        assert_eq!(d.furnished, Some(false));
+
+        // "no" is "from" in Latvian
+        let d = analyze("5 min no stāvvietas, mājdzīvnieki nav atļauti");
+        assert!(d.park);
+        assert_eq!(d.pets, Some(false));
+
+        let d = analyze("No pets, no parking. Unfurnished");
+        assert!(!d.park);
+        assert_eq!(d.pets, Some(false));
+        assert_eq!(d.furnished, Some(false));
+    }
+
+    #[test]
+    fn handles_commission() {
+        assert_eq!(analyze("Комиссия агентства 50%.").commission, Some(true));
+        assert_eq!(
+            analyze("Agency commission one month rent").commission,
+            Some(true)
+        );
+        assert_eq!(analyze("Комиссия 0%").commission, Some(false));
+        assert_eq!(analyze("0% комиссии, депозит").commission, Some(false));
+        assert_eq!(analyze("Komisija 0 %").commission, Some(false));
+        assert_eq!(analyze("Без комиссии агентству!").commission, Some(false));
+        assert_eq!(analyze("Bez komisijas maksas.").commission, Some(false));
+        assert_eq!(analyze("Bezkomisijas").commission, Some(false));
+        assert_eq!(
+            analyze("No commission, commission free").commission,
+            Some(false)
+        );
+        assert_eq!(
+            analyze("Комиссия агентству не взимается").commission,
+            Some(false)
+        );
+        assert_eq!(analyze("Светлая квартира").commission, None);
+    }
+
+    #[test]
+    fn handles_mixed_languages() {
+        // The negation ends at "но" and does not cross the clauses
+        let d = analyze("Без мебели, но с балконом. Parking available; pets not allowed");
+        assert_eq!(d.furnished, Some(false));
+        assert!(d.balkony && d.park);
+        assert_eq!(d.pets, Some(false));
+
+        // A positive mention wins over a negated one
+        let d = analyze("Нет парковки у дома. Bet ir garāža");
+        assert!(d.park);
+    }
+
+    #[test]
+    fn parses_deposits() {
+        let deposit = |text: &str| analyze(text).deposit;
+        assert_eq!(deposit("Депозит 2 месяца"), Some(Deposit::Months(2)));
+        assert_eq!(
+            deposit("drošības nauda 1 000 eur"),
+            Some(Deposit::Amount(1000))
+        );
+        assert_eq!(deposit("Deposit two months"), Some(Deposit::Months(2)));
+        assert_eq!(deposit("без депозита"), Some(Deposit::NoDeposit));
+        assert_eq!(deposit("no deposit"), Some(Deposit::NoDeposit));
+        assert_eq!(deposit("залог есть"), Some(Deposit::Mentioned));
+        // A bare number up to 12 is months, a larger one is the amount
+        assert_eq!(deposit("депозит 12"), Some(Deposit::Months(12)));
+        assert_eq!(deposit("депозит 13"), Some(Deposit::Amount(13)));
+        assert_eq!(deposit("депозит 600"), Some(Deposit::Amount(600)));
+        assert_eq!(
+            deposit("депозит 1 000 000 000 000 eur"),
+            Some(Deposit::Mentioned)
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::{config::Config, error::SSError};
+
+/// Outcome of parsing a single field of the page
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldStatus {
+    Ok,
+    /// Selector found nothing
+    Missing,
+    /// Something is found, but it can not be parsed; holds the raw value
+    Malformed(String),
+}
+
+impl FieldStatus {
+    pub fn of<T>(result: &Result<T, SSError>) -> Self {
+        match result {
+            Ok(_) => FieldStatus::Ok,
+            Err(SSError::Selector(_)) => FieldStatus::Missing,
+            Err(SSError::FieldParse { raw, .. }) => FieldStatus::Malformed(raw.clone()),
+            Err(e) => FieldStatus::Malformed(e.to_string()),
+        }
+    }
+}
+
+/// Per-field statuses of a parsed page
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    pub url: String,
+    pub fields: BTreeMap<&'static str, FieldStatus>,
+}
+
+impl ParseReport {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Records the status of the field and passes the result through
+    pub fn track<T>(
+        &mut self,
+        field: &'static str,
+        result: Result<T, SSError>,
+    ) -> Result<T, SSError> {
+        let status = FieldStatus::of(&result);
+        if let FieldStatus::Malformed(raw) = &status {
+            log::debug!("Field '{}' of '{}' is malformed: {}", field, self.url, raw);
+        }
+        self.fields.insert(field, status);
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FieldStats {
+    pub ok: usize,
+    pub missing: usize,
+    pub malformed: usize,
+}
+
+impl FieldStats {
+    pub fn total(&self) -> usize {
+        self.ok + self.missing + self.malformed
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.ok as f64 / total as f64,
+        }
+    }
+}
+
+/// Success rate of a field dropped compared to the previous cycles
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthAlert {
+    pub field: &'static str,
+    pub rate: f64,
+    pub baseline: f64,
+}
+
+/// Field statistics of a single crawl cycle
+#[derive(Debug, Clone, Default)]
+pub struct CycleHealth {
+    pub started: chrono::NaiveDateTime,
+    pub finished: Option<chrono::NaiveDateTime>,
+    pub pages: usize,
+    pub fields: BTreeMap<&'static str, FieldStats>,
+    pub alerts: Vec<HealthAlert>,
+}
+
+impl CycleHealth {
+    /// "field: ok/total (rate%)" per line
+    pub fn report(&self) -> String {
+        self.fields
+            .iter()
+            .map(|(field, stats)| {
+                format!(
+                    "{}: {}/{} ({:.0}%)",
+                    field,
+                    stats.ok,
+                    stats.total(),
+                    stats.success_rate() * 100.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Aggregates parse reports over crawl cycles and detects sudden drops of
+/// the field success rates, which usually mean that ss.lv changed the markup
+#[derive(Debug)]
+pub struct ParserHealth {
+    current: CycleHealth,
+    history: VecDeque<CycleHealth>,
+    // Fields already alerted about, so the alert is not repeated every cycle
+    alerted: BTreeSet<&'static str>,
+    window: usize,
+    min_samples: usize,
+    drop_threshold: f64,
+}
+
+impl Default for ParserHealth {
+    fn default() -> Self {
+        Self::new(
+            Config::health_window(),
+            Config::health_min_samples(),
+            Config::health_drop_threshold(),
+        )
+    }
+}
+
+impl ParserHealth {
+    pub fn new(window: usize, min_samples: usize, drop_threshold: f64) -> Self {
+        Self {
+            current: CycleHealth::default(),
+            history: VecDeque::new(),
+            alerted: BTreeSet::new(),
+            window,
+            min_samples,
+            drop_threshold,
+        }
+    }
+
+    pub fn start_cycle(&mut self) {
+        self.current = CycleHealth {
+            started: chrono::Local::now().naive_local(),
+            ..Default::default()
+        };
+    }
+
+    pub fn record(&mut self, report: &ParseReport) {
+        self.current.pages += 1;
+        for (field, status) in report.fields.iter() {
+            let stats = self.current.fields.entry(field).or_default();
+            match status {
+                FieldStatus::Ok => stats.ok += 1,
+                FieldStatus::Missing => stats.missing += 1,
+                FieldStatus::Malformed(_) => stats.malformed += 1,
+            }
+        }
+    }
+
+    /// Mean success rate of the field over the previous cycles with enough samples
+    fn baseline(&self, field: &str) -> Option<f64> {
+        let rates: Vec<f64> = self
+            .history
+            .iter()
+            .filter_map(|c| c.fields.get(field))
+            .filter(|s| s.total() >= self.min_samples)
+            .map(|s| s.success_rate())
+            .collect();
+        match rates.len() {
+            0 => None,
+            n => Some(rates.iter().sum::<f64>() / n as f64),
+        }
+    }
+
+    pub fn finish_cycle(&mut self) -> CycleHealth {
+        let mut cycle = std::mem::take(&mut self.current);
+        cycle.finished = Some(chrono::Local::now().naive_local());
+        let dropped: Vec<HealthAlert> = cycle
+            .fields
+            .iter()
+            .filter(|(_, stats)| stats.total() >= self.min_samples)
+            .filter_map(|(field, stats)| {
+                let baseline = self.baseline(field)?;
+                let rate = stats.success_rate();
+                (baseline - rate >= self.drop_threshold).then_some(HealthAlert {
+                    field,
+                    rate,
+                    baseline,
+                })
+            })
+            .collect();
+        self.alerted
+            .retain(|field| dropped.iter().any(|a| a.field == *field));
+        cycle.alerts = dropped
+            .into_iter()
+            .filter(|a| self.alerted.insert(a.field))
+            .collect();
+        self.history.push_back(cycle.clone());
+        while self.history.len() > self.window {
+            self.history.pop_front();
+        }
+        cycle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(fields: &[(&'static str, FieldStatus)]) -> ParseReport {
+        ParseReport {
+            url: String::new(),
+            fields: fields.iter().cloned().collect(),
+        }
+    }
+
+    fn cycle(health: &mut ParserHealth, pages: usize, area: FieldStatus) -> CycleHealth {
+        health.start_cycle();
+        for _ in 0..pages {
+            health.record(&report(&[
+                ("area", area.clone()),
+                ("price", FieldStatus::Ok),
+            ]));
+        }
+        health.finish_cycle()
+    }
+
+    #[test]
+    fn alerts_on_sudden_drop() {
+        let mut health = ParserHealth::new(3, 2, 0.5);
+        assert!(cycle(&mut health, 4, FieldStatus::Ok).alerts.is_empty());
+        assert!(cycle(&mut health, 4, FieldStatus::Ok).alerts.is_empty());
+        let broken = cycle(&mut health, 4, FieldStatus::Missing);
+        assert_eq!(
+            broken.alerts,
+            vec![HealthAlert {
+                field: "area",
+                rate: 0.0,
+                baseline: 1.0
+            }]
+        );
+    }
+
+    #[test]
+    fn alerts_once_per_drop() {
+        let mut health = ParserHealth::new(3, 2, 0.5);
+        cycle(&mut health, 4, FieldStatus::Ok);
+        assert_eq!(cycle(&mut health, 4, FieldStatus::Missing).alerts.len(), 1);
+        assert!(cycle(&mut health, 4, FieldStatus::Missing)
+            .alerts
+            .is_empty());
+    }
+
+    #[test]
+    fn ignores_small_cycles() {
+        let mut health = ParserHealth::new(3, 5, 0.5);
+        cycle(&mut health, 10, FieldStatus::Ok);
+        assert!(cycle(&mut health, 2, FieldStatus::Missing)
+            .alerts
+            .is_empty());
+    }
+}
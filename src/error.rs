@@ -2,18 +2,172 @@ use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum SSError {
-    Index(usize),
-    ConvertChar(u32),
-    Http(String),
+    /// Transport level failure: DNS, connection, timeout, broken body
+    Network(reqwest::Error),
+    /// Server answered with an unexpected status
+    HttpStatus {
+        url: String,
+        status: u16,
+    },
+    /// Invalid url or request body
+    Request(String),
+    /// Selector is invalid or matches nothing
     Selector(String),
-    Parse(String),
+    /// Value of a field is there, but can not be parsed
+    FieldParse {
+        field: String,
+        raw: String,
+    },
+    /// Invalid selector spec file
+    Spec(String),
+    /// Invalid settings file
+    Config(String),
+    /// Requested entity does not exist
+    NotFound(String),
+    /// Entity with the same key exists already, or the change would leave
+    /// the data inconsistent
+    Conflict(String),
+    /// Missing or wrong api token
+    Unauthorized,
+    /// Request is refused whoever makes it, e.g. a form of another site
+    Forbidden(String),
+    /// Embedded web server failure
+    Web(String),
+    /// Search filters are not applied by ss.lv
+    Session(String),
+    /// Too many consecutive failures, requests are paused until the given time
+    CircuitOpen(chrono::NaiveDateTime),
+    /// Obfuscated content can not be decoded
+    Decode(String),
+    /// Local file or output failure
+    Io(std::io::Error),
+    /// Malformed JSON or a value which does not fit
+    Json(serde_json::Error),
+    Pattern(regex::Error),
+    Db(rusqlite::Error),
+    Notifier(teloxide::RequestError),
     Empty,
 }
 
-impl std::error::Error for SSError {}
+impl SSError {
+    pub fn field(field: &str, raw: impl Display) -> Self {
+        Self::FieldParse {
+            field: field.to_string(),
+            raw: raw.to_string(),
+        }
+    }
+}
+
+impl std::error::Error for SSError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SSError::Network(e) => Some(e),
+            SSError::Io(e) => Some(e),
+            SSError::Json(e) => Some(e),
+            SSError::Pattern(e) => Some(e),
+            SSError::Db(e) => Some(e),
+            SSError::Notifier(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl Display for SSError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        match self {
+            SSError::Network(e) => write!(f, "network error: {}", e),
+            SSError::HttpStatus { url, status } => {
+                write!(f, "unexpected http status {} of '{}'", status, url)
+            }
+            SSError::Request(e) => write!(f, "invalid request: {}", e),
+            SSError::Selector(s) => write!(f, "nothing found by selector '{}'", s),
+            SSError::FieldParse { field, raw } => {
+                write!(f, "fail to parse field '{}' out of '{}'", field, raw)
+            }
+            SSError::Spec(e) => write!(f, "invalid selector spec: {}", e),
+            SSError::Config(e) => write!(f, "invalid settings: {}", e),
+            SSError::NotFound(e) => write!(f, "not found: {}", e),
+            SSError::Conflict(e) => write!(f, "conflict: {}", e),
+            SSError::Unauthorized => write!(f, "unauthorized"),
+            SSError::Forbidden(e) => write!(f, "forbidden: {}", e),
+            SSError::Web(e) => write!(f, "web server: {}", e),
+            SSError::Session(e) => write!(f, "search session: {}", e),
+            SSError::CircuitOpen(until) => {
+                write!(f, "requests are paused until {}", until.format("%H:%M:%S"))
+            }
+            SSError::Decode(e) => write!(f, "fail to decode: {}", e),
+            SSError::Io(e) => write!(f, "io error: {}", e),
+            SSError::Json(e) => write!(f, "invalid json: {}", e),
+            SSError::Pattern(e) => write!(f, "invalid pattern: {}", e),
+            SSError::Db(e) => write!(f, "database error: {}", e),
+            SSError::Notifier(e) => write!(f, "notifier error: {}", e),
+            SSError::Empty => write!(f, "empty"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for SSError {
+    fn from(value: reqwest::Error) -> Self {
+        SSError::Network(value)
+    }
+}
+
+impl From<url::ParseError> for SSError {
+    fn from(value: url::ParseError) -> Self {
+        SSError::Request(value.to_string())
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for SSError {
+    fn from(value: serde_urlencoded::ser::Error) -> Self {
+        SSError::Request(value.to_string())
+    }
+}
+
+impl From<std::io::Error> for SSError {
+    fn from(value: std::io::Error) -> Self {
+        SSError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SSError {
+    fn from(value: serde_json::Error) -> Self {
+        SSError::Json(value)
+    }
+}
+
+impl From<csv::Error> for SSError {
+    fn from(value: csv::Error) -> Self {
+        SSError::Io(value.into())
+    }
+}
+
+impl From<regex::Error> for SSError {
+    fn from(value: regex::Error) -> Self {
+        SSError::Pattern(value)
+    }
+}
+
+impl From<rusqlite::Error> for SSError {
+    fn from(value: rusqlite::Error) -> Self {
+        SSError::Db(value)
+    }
+}
+
+impl From<teloxide::RequestError> for SSError {
+    fn from(value: teloxide::RequestError) -> Self {
+        SSError::Notifier(value)
+    }
+}
+
+impl From<base64::DecodeError> for SSError {
+    fn from(value: base64::DecodeError) -> Self {
+        SSError::Decode(value.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for SSError {
+    fn from(value: std::str::Utf8Error) -> Self {
+        SSError::Decode(value.to_string())
     }
 }
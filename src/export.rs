@@ -0,0 +1,249 @@
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    db::{
+        record::{ApartmentRecrod, RecordQuery},
+        status::ListingStatus,
+    },
+    error::SSError,
+};
+
+/// Stored apartment, the latest record of it
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    pub id: String,
+    pub url: String,
+    /// Profile of the latest record
+    pub profile: String,
+    /// Local time the listing was sent, "%Y-%m-%d %H:%M:%S"
+    pub datetime: String,
+    pub price: String,
+    pub price_eur: Option<i64>,
+    pub rooms: Option<i64>,
+    pub area: Option<f64>,
+    pub district: Option<String>,
+    pub address: Option<String>,
+    pub floor: Option<i64>,
+    pub total_floors: Option<i64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Where the coordinates come from: map, nominatim or dataset
+    pub location_source: Option<String>,
+    /// exact, house, street, district or city
+    pub location_precision: Option<String>,
+    /// Meters to the target location
+    pub distance: Option<i64>,
+    pub status: &'static str,
+}
+
+impl Listing {
+    pub fn new(r: ApartmentRecrod, status: ListingStatus) -> Self {
+        Self {
+            id: r.id.value,
+            url: r.url.value,
+            profile: r.profile.value,
+            datetime: r.datetime.value,
+            price: r.price.value,
+            price_eur: r.price_eur.value,
+            rooms: r.rooms.value,
+            area: r.area.value,
+            district: r.district.value,
+            address: r.address.value,
+            floor: r.floor.value,
+            total_floors: r.total_floors.value,
+            latitude: r.latitude.value,
+            longitude: r.longitude.value,
+            location_source: r.location_source.value,
+            location_precision: r.location_precision.value,
+            distance: r.distance.value,
+            status: status.as_str(),
+        }
+    }
+
+    /// The stored apartments matching the query
+    pub fn select(query: &RecordQuery) -> Result<Vec<Self>, SSError> {
+        Ok(query
+            .select()?
+            .into_iter()
+            .map(|(record, status)| Self::new(record, status))
+            .collect())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Format {
+    Csv,
+    /// A json object per line
+    #[default]
+    Jsonl,
+    /// FeatureCollection, the location is the point geometry
+    GeoJson,
+}
+
+impl Format {
+    pub const ALL: [Self; 3] = [Self::Csv, Self::Jsonl, Self::GeoJson];
+
+    /// Also the file extension
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+            Self::GeoJson => "geojson",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, SSError> {
+        let s = s.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.as_str() == s)
+            .ok_or(SSError::Request(format!("unknown export format '{}'", s)))
+    }
+
+    /// Of the file name, "listings.csv" is csv
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        Self::parse(extension).ok()
+    }
+
+    pub fn write(&self, listings: &[Listing], out: &mut impl Write) -> Result<(), SSError> {
+        match self {
+            Self::Csv => write_csv(listings, out),
+            Self::Jsonl => write_jsonl(listings, out),
+            Self::GeoJson => write_geojson(listings, out),
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = SSError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// A listing per line
+pub fn write_jsonl(listings: &[Listing], out: &mut impl Write) -> Result<(), SSError> {
+    for listing in listings {
+        serde_json::to_writer(&mut *out, listing)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Header row and a row per listing, the missing values are empty
+pub fn write_csv(listings: &[Listing], out: &mut impl Write) -> Result<(), SSError> {
+    let mut writer = csv::Writer::from_writer(out);
+    for listing in listings {
+        writer.serialize(listing)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Listings without the coordinates have no geometry
+pub fn write_geojson(listings: &[Listing], out: &mut impl Write) -> Result<(), SSError> {
+    let features = listings
+        .iter()
+        .map(feature)
+        .collect::<Result<Vec<_>, SSError>>()?;
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    serde_json::to_writer(&mut *out, &collection)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn feature(listing: &Listing) -> Result<Value, SSError> {
+    let mut properties = serde_json::to_value(listing)?;
+    if let Some(properties) = properties.as_object_mut() {
+        properties.remove("latitude");
+        properties.remove("longitude");
+    }
+    let geometry = match (listing.latitude, listing.longitude) {
+        (Some(latitude), Some(longitude)) => json!({
+            "type": "Point",
+            "coordinates": [longitude, latitude],
+        }),
+        _ => Value::Null,
+    };
+    Ok(json!({
+        "type": "Feature",
+        "id": listing.id,
+        "geometry": geometry,
+        "properties": properties,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(id: &str, location: Option<(f64, f64)>) -> Listing {
+        Listing {
+            id: id.to_string(),
+            url: format!("https://www.ss.lv/msg/{}.html", id),
+            profile: "default".to_string(),
+            datetime: "2023-06-01 12:00:00".to_string(),
+            price: "600 €/мес.".to_string(),
+            price_eur: Some(600),
+            rooms: Some(2),
+            area: Some(54.5),
+            district: Some("Центр".to_string()),
+            address: Some("Brīvības 1, \"A\"".to_string()),
+            floor: None,
+            total_floors: None,
+            latitude: location.map(|l| l.0),
+            longitude: location.map(|l| l.1),
+            location_source: location.map(|_| "map".to_string()),
+            location_precision: location.map(|_| "exact".to_string()),
+            distance: None,
+            status: "new",
+        }
+    }
+
+    #[test]
+    fn writes_csv() {
+        let mut out = vec![];
+        write_csv(&[listing("a", Some((56.95, 24.1)))], &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("id,url,profile,datetime,price,"));
+        let row = lines.next().unwrap();
+        assert!(row.contains(r#","Brīvības 1, ""A""","#));
+        assert!(row.contains(",,,56.95,24.1,map,exact,,new"));
+    }
+
+    #[test]
+    fn writes_geojson() {
+        let mut out = vec![];
+        let listings = [listing("a", Some((56.95, 24.1))), listing("b", None)];
+        write_geojson(&listings, &mut out).unwrap();
+        let collection: Value = serde_json::from_slice(&out).unwrap();
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([24.1, 56.95]));
+        assert_eq!(features[0]["properties"]["price_eur"], 600);
+        assert!(features[0]["properties"].get("latitude").is_none());
+        assert!(features[1]["geometry"].is_null());
+    }
+
+    #[test]
+    fn parses_format() {
+        assert_eq!(Format::parse("CSV").unwrap(), Format::Csv);
+        assert_eq!(
+            Format::from_path("out/flats.geojson"),
+            Some(Format::GeoJson)
+        );
+        assert_eq!(Format::from_path("flats"), None);
+        assert!(Format::parse("xlsx").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    analytics::DealScore,
+    apartment::{Apartment, ListingSummary},
+    area::AreaFilter,
+    config::Config,
+    db::profile::SearchProfile,
+};
+
+/// Conditions an apartment has to meet before it gets notified
+#[derive(Debug, Clone)]
+pub struct ApartmentFilter {
+    pub price_low: u32,
+    pub price_high: u32,
+    pub area_low: u32,
+    pub allow_ground_floor: bool,
+    pub allow_top_floor_without_elevator: bool,
+    pub allow_agency: bool,
+    pub allow_commission: bool,
+    pub max_deal_percentile: Option<f64>,
+    /// Longest commute by destination name
+    pub max_commute_minutes: Vec<(String, u32)>,
+    /// Polygons by profile name, see `with_areas`
+    pub areas: BTreeMap<String, AreaFilter>,
+}
+
+impl Default for ApartmentFilter {
+    fn default() -> Self {
+        Self::from_config()
+    }
+}
+
+impl ApartmentFilter {
+    pub fn from_config() -> Self {
+        Self {
+            price_low: Config::price_low(),
+            price_high: Config::price_high(),
+            area_low: Config::area_low(),
+            allow_ground_floor: Config::allow_ground_floor(),
+            allow_top_floor_without_elevator: Config::allow_top_floor_without_elevator(),
+            allow_agency: Config::allow_agency(),
+            allow_commission: Config::allow_commission(),
+            max_deal_percentile: Config::max_deal_percentile(),
+            max_commute_minutes: Config::commute_destinations()
+                .iter()
+                .filter_map(|d| Some((d.name.clone(), d.max_minutes?)))
+                .collect(),
+            areas: BTreeMap::new(),
+        }
+    }
+
+    /// Same conditions with the areas of the profiles; the areas of a
+    /// profile which fail to load are left out
+    pub fn with_areas(&self, profiles: &[SearchProfile]) -> Self {
+        let mut areas = BTreeMap::new();
+        for profile in profiles {
+            match AreaFilter::for_profile(profile) {
+                Ok(Some(filter)) => {
+                    areas.insert(profile.name.value.clone(), filter);
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("Areas of profile '{}': {}", profile.name.value, e),
+            }
+        }
+        Self {
+            areas,
+            ..self.clone()
+        }
+    }
+
+    /// Same conditions with the price and area limits of the profile
+    pub fn for_profile(&self, profile: &SearchProfile) -> Self {
+        Self {
+            price_low: profile.price_low.value,
+            price_high: profile.price_high.value,
+            area_low: profile.area_low.value,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the reason of the rejection or None if the apartment passes
+    pub fn reject_reason(&self, a: &Apartment) -> Option<String> {
+        if !self.allow_ground_floor && a.is_ground_floor {
+            return Some("ground floor".to_string());
+        }
+        if !self.allow_top_floor_without_elevator && a.is_top_floor && !a.has_elevator() {
+            return Some(format!("top floor {} without elevator", a.floor_brief()));
+        }
+        if !self.allow_agency && a.is_agency {
+            return Some("agency listing".to_string());
+        }
+        if !self.allow_commission && a.commission == Some(true) {
+            return Some("commission fee".to_string());
+        }
+        for (destination, max) in self.max_commute_minutes.iter() {
+            let commute = a.commutes.iter().find(|c| c.destination == *destination);
+            if let Some(commute) = commute.filter(|c| c.minutes() > *max as i64) {
+                return Some(format!(
+                    "{} min to {} by {}",
+                    commute.minutes(),
+                    destination,
+                    commute.mode.as_str()
+                ));
+            }
+        }
+        None
+    }
+
+    /// Rejects the apartment outside the areas of the profile, a profile
+    /// without areas takes any
+    pub fn area_reject_reason(&self, a: &Apartment, profile: &str) -> Option<String> {
+        self.areas.get(profile)?.reject_reason(a)
+    }
+
+    /// Rejects the apartment which is not cheap enough against the similar
+    /// listings, the one without a score passes
+    pub fn deal_reject_reason(&self, score: Option<&DealScore>) -> Option<String> {
+        let (max, score) = (self.max_deal_percentile?, score?);
+        if score.percentile > max {
+            return Some(format!(
+                "price per m² at {:.0} percentile of {} similar listings",
+                score.percentile, score.comparables
+            ));
+        }
+        None
+    }
+
+    /// Same for the search results row, unknown values pass; a 0 limit is
+    /// no limit
+    pub fn summary_reject_reason(&self, s: &ListingSummary) -> Option<String> {
+        if let Some(price) = s
+            .price
+            .filter(|p| *p < self.price_low || (self.price_high > 0 && *p > self.price_high))
+        {
+            return Some(format!("price {}", price));
+        }
+        if let Some(area) = s.area.filter(|a| *a < self.area_low as f64) {
+            return Some(format!("area {}", area));
+        }
+        if !self.allow_ground_floor && s.is_ground_floor() {
+            return Some("ground floor".to_string());
+        }
+        None
+    }
+
+    pub fn accept(&self, a: &Apartment) -> bool {
+        self.reject_reason(a).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apartment::ApartmentDescription,
+        commute::{Commute, TravelMode},
+    };
+
+    fn strict() -> ApartmentFilter {
+        ApartmentFilter {
+            allow_ground_floor: false,
+            allow_top_floor_without_elevator: false,
+            allow_agency: false,
+            allow_commission: false,
+            max_commute_minutes: vec![("работа".into(), 30)],
+            ..ApartmentFilter::from_config()
+        }
+    }
+
+    #[test]
+    fn rejects_apartments() {
+        let filter = strict();
+        let mut a = Apartment::default();
+        assert_eq!(filter.reject_reason(&a), None);
+
+        a.is_ground_floor = true;
+        assert_eq!(filter.reject_reason(&a).as_deref(), Some("ground floor"));
+        a.is_ground_floor = false;
+
+        (a.floor, a.total_floors, a.is_top_floor) = (Some(5), Some(5), true);
+        assert_eq!(
+            filter.reject_reason(&a).as_deref(),
+            Some("top floor 5/5 (посл.) without elevator")
+        );
+        // The elevator of the description counts as well
+        a.description = Some(ApartmentDescription {
+            elevator: true,
+            ..Default::default()
+        });
+        assert_eq!(filter.reject_reason(&a), None);
+
+        a.is_agency = true;
+        assert_eq!(filter.reject_reason(&a).as_deref(), Some("agency listing"));
+        a.is_agency = false;
+
+        a.commission = Some(true);
+        assert_eq!(filter.reject_reason(&a).as_deref(), Some("commission fee"));
+        a.commission = None;
+        assert_eq!(filter.reject_reason(&a), None);
+
+        a.commutes = vec![Commute {
+            destination: "работа".into(),
+            mode: TravelMode::Transit,
+            seconds: 31 * 60,
+            meters: None,
+        }];
+        assert_eq!(
+            filter.reject_reason(&a).as_deref(),
+            Some("31 min to работа by transit")
+        );
+    }
+
+    #[test]
+    fn allows_what_is_allowed() {
+        let filter = ApartmentFilter {
+            allow_ground_floor: true,
+            allow_top_floor_without_elevator: true,
+            allow_agency: true,
+            allow_commission: true,
+            ..strict()
+        };
+        let a = Apartment {
+            is_ground_floor: true,
+            is_top_floor: true,
+            is_agency: true,
+            commission: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(filter.reject_reason(&a), None);
+    }
+}
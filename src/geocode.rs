@@ -0,0 +1,355 @@
+use std::{collections::HashMap, io::Read, time::Duration};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    apartment::{Location, LocationSource, Precision},
+    config::Config,
+    db::geocode::GeocodeRecord,
+    error::SSError,
+};
+
+const USER_AGENT: &str = concat!("rentbot_sslv/", env!("CARGO_PKG_VERSION"));
+
+/// Lowercase, without the Latvian diacritics, the punctuation and "iela",
+/// ss.lv writes the streets without it
+pub fn normalize(s: &str) -> String {
+    let plain: String = s
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ā' => 'a',
+            'č' => 'c',
+            'ē' => 'e',
+            'ģ' => 'g',
+            'ī' => 'i',
+            'ķ' => 'k',
+            'ļ' => 'l',
+            'ņ' => 'n',
+            'š' => 's',
+            'ū' => 'u',
+            'ž' => 'z',
+            '.' | ',' | '"' | '\'' => ' ',
+            c => c,
+        })
+        .collect();
+    plain
+        .split_whitespace()
+        .filter(|w| !matches!(*w, "iela" | "iel"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Normalised street and house of "Krišjāņa Barona 50 k-2": the house is
+/// everything from the first word starting with a digit, without spaces
+pub fn parse_address(address: &str) -> (String, Option<String>) {
+    let address = normalize(address);
+    let words: Vec<&str> = address.split(' ').collect();
+    match words
+        .iter()
+        .position(|w| w.starts_with(|c: char| c.is_ascii_digit()))
+    {
+        Some(i) => (words[..i].join(" "), Some(words[i..].concat())),
+        None => (address.clone(), None),
+    }
+}
+
+/// Row of the dataset file
+#[derive(Debug, Deserialize)]
+struct AddressRow {
+    street: String,
+    house: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Offline street and house number coordinates, e.g. an export of the
+/// state address register
+#[derive(Debug, Default)]
+pub struct Dataset {
+    /// Normalised street to the houses
+    streets: HashMap<String, Vec<(String, f64, f64)>>,
+}
+
+impl Dataset {
+    /// CSV with the header: street,house,latitude,longitude
+    pub fn load(reader: impl Read) -> Result<Self, SSError> {
+        let mut dataset = Self::default();
+        for row in csv::Reader::from_reader(reader).deserialize() {
+            let row: AddressRow = row?;
+            let house = normalize(&row.house).replace(' ', "");
+            dataset
+                .streets
+                .entry(normalize(&row.street))
+                .or_default()
+                .push((house, row.latitude, row.longitude));
+        }
+        Ok(dataset)
+    }
+
+    /// The house, or the middle of the street when the house is unknown
+    pub fn locate(&self, address: &str) -> Option<Location> {
+        let (street, house) = parse_address(address);
+        let houses = self.streets.get(&street)?;
+        if let Some(&(_, latitude, longitude)) = house
+            .as_ref()
+            .and_then(|house| houses.iter().find(|(h, _, _)| h == house))
+        {
+            return Some(Location {
+                latitude,
+                longitude,
+                source: LocationSource::Dataset,
+                precision: Precision::House,
+            });
+        }
+        let count = houses.len() as f64;
+        Some(Location {
+            latitude: houses.iter().map(|h| h.1).sum::<f64>() / count,
+            longitude: houses.iter().map(|h| h.2).sum::<f64>() / count,
+            source: LocationSource::Dataset,
+            precision: Precision::Street,
+        })
+    }
+}
+
+/// Precision of the `addresstype` of Nominatim
+fn nominatim_precision(addresstype: &str) -> Precision {
+    match addresstype {
+        "building" | "house" | "amenity" => Precision::House,
+        "suburb" | "neighbourhood" | "quarter" | "city_district" | "borough" => Precision::District,
+        "city" | "town" | "village" | "municipality" | "county" | "state" => Precision::City,
+        _ => Precision::Street,
+    }
+}
+
+/// `[{"lat": "56.95", "lon": "24.11", "addresstype": "building"}]`, format
+/// jsonv2; None when nothing is found
+pub fn parse_nominatim(body: &Value) -> Result<Option<Location>, SSError> {
+    let Some(places) = body.as_array() else {
+        return Err(SSError::Request(format!(
+            "Nominatim: {}",
+            body["error"]["message"]
+                .as_str()
+                .or(body["error"].as_str())
+                .unwrap_or("unexpected answer")
+        )));
+    };
+    let Some(place) = places.first() else {
+        return Ok(None);
+    };
+    let coordinate = |key: &str| {
+        place[key]
+            .as_str()
+            .and_then(|c| c.parse::<f64>().ok())
+            .ok_or(SSError::Request(format!("Nominatim: no {}", key)))
+    };
+    Ok(Some(Location {
+        latitude: coordinate("lat")?,
+        longitude: coordinate("lon")?,
+        source: LocationSource::Nominatim,
+        precision: nominatim_precision(place["addresstype"].as_str().unwrap_or_default()),
+    }))
+}
+
+enum Source {
+    Nominatim {
+        url: String,
+        client: reqwest::Client,
+    },
+    Dataset(Dataset),
+}
+
+/// Coordinates of the address when the ad has no map link; the sources are
+/// tried in order and the most precise answer wins
+pub struct Geocoder {
+    sources: Vec<Source>,
+    retry_days: i64,
+}
+
+impl Geocoder {
+    /// None unless some sources are configured, the dataset is loaded here
+    pub fn from_config() -> Result<Option<Self>, SSError> {
+        let settings = &Config::settings().geocoder;
+        if settings.sources.is_empty() {
+            return Ok(None);
+        }
+        let mut sources = vec![];
+        for source in settings.sources.iter() {
+            match source {
+                LocationSource::Map => {
+                    return Err(SSError::Config("geocoder: 'map' is not a geocoder".into()))
+                }
+                LocationSource::Nominatim => {
+                    if settings.url.is_empty() {
+                        return Err(SSError::Config("geocoder: no url of Nominatim".into()));
+                    }
+                    let client = reqwest::Client::builder()
+                        .timeout(Duration::from_secs(settings.timeout_secs))
+                        .user_agent(USER_AGENT)
+                        .build()?;
+                    sources.push(Source::Nominatim {
+                        url: settings.url.trim_end_matches('/').to_string(),
+                        client,
+                    });
+                }
+                LocationSource::Dataset => {
+                    let file = std::fs::File::open(&settings.dataset).map_err(|e| {
+                        SSError::Config(format!("geocoder dataset '{}': {}", settings.dataset, e))
+                    })?;
+                    let dataset = Dataset::load(file)?;
+                    log::info!(
+                        "Loaded {} street(s) of the geocoder dataset",
+                        dataset.streets.len()
+                    );
+                    sources.push(Source::Dataset(dataset));
+                }
+            }
+        }
+        Ok(Some(Self {
+            sources,
+            retry_days: settings.retry_days,
+        }))
+    }
+
+    /// None if no source knows the address
+    pub async fn locate(&self, city: &str, district: &str, address: &str) -> Option<Location> {
+        let mut best: Option<Location> = None;
+        for source in self.sources.iter() {
+            let found = match source {
+                Source::Dataset(dataset) => dataset.locate(address),
+                Source::Nominatim { url, client } => {
+                    match self
+                        .nominatim_cached(url, client, city, district, address)
+                        .await
+                    {
+                        Ok(found) => found,
+                        Err(e) => {
+                            log::error!("Fail to geocode '{}, {}': {}", address, city, e);
+                            None
+                        }
+                    }
+                }
+            };
+            if let Some(found) = found {
+                if best.as_ref().is_none_or(|b| found.precision < b.precision) {
+                    best = Some(found);
+                }
+            }
+            if best
+                .as_ref()
+                .is_some_and(|b| b.precision <= Precision::House)
+            {
+                break;
+            }
+        }
+        best
+    }
+
+    async fn nominatim_cached(
+        &self,
+        url: &str,
+        client: &reqwest::Client,
+        city: &str,
+        district: &str,
+        address: &str,
+    ) -> Result<Option<Location>, SSError> {
+        let key = normalize(&format!("{}|{}|{}", city, district, address));
+        let mut record = GeocodeRecord::key(LocationSource::Nominatim, &key);
+        match record.select(self.retry_days) {
+            Ok(Some(cached)) => return Ok(cached),
+            Ok(None) => {}
+            Err(e) => log::error!("Fail to look up the geocoder cache: {}", e),
+        }
+        let mut found = None;
+        if !address.is_empty() {
+            let query = [
+                ("street", address),
+                ("city", city),
+                ("countrycodes", "lv"),
+                ("format", "jsonv2"),
+                ("limit", "1"),
+            ];
+            found = Self::nominatim(client, url, &query).await?;
+        }
+        // ss.lv districts are Riga neighbourhoods, good enough for a distance
+        if found.is_none() && !district.is_empty() {
+            let q = format!("{}, {}", district, city);
+            let query = [
+                ("q", q.as_str()),
+                ("countrycodes", "lv"),
+                ("format", "jsonv2"),
+                ("limit", "1"),
+            ];
+            found = Self::nominatim(client, url, &query)
+                .await?
+                .map(|mut location| {
+                    location.precision = location.precision.max(Precision::District);
+                    location
+                });
+        }
+        if let Err(e) = record.insert(found.as_ref()) {
+            log::error!("Fail to save the geocoded address: {}", e);
+        }
+        Ok(found)
+    }
+
+    async fn nominatim(
+        client: &reqwest::Client,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Option<Location>, SSError> {
+        let body: Value = client
+            .get(format!("{}/search", url))
+            .query(query)
+            .send()
+            .await?
+            .json()
+            .await?;
+        parse_nominatim(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(normalize("Krišjāņa Barona iela"), "krisjana barona");
+        assert_eq!(
+            parse_address("Krišjāņa Barona 50 k-2"),
+            ("krisjana barona".to_string(), Some("50k-2".to_string()))
+        );
+        assert_eq!(
+            parse_address("Ģertrūdes iela"),
+            ("gertrudes".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn locates_in_dataset() {
+        let csv = "street,house,latitude,longitude\n\
+                   Tērbatas iela,73,56.9600,24.1400\n\
+                   Tērbatas iela,75,56.9610,24.1420\n";
+        let dataset = Dataset::load(csv.as_bytes()).unwrap();
+        let house = dataset.locate("Tērbatas 75").unwrap();
+        assert_eq!(house.precision, Precision::House);
+        assert_eq!(house.latitude, 56.961);
+        let street = dataset.locate("Tērbatas 1").unwrap();
+        assert_eq!(street.precision, Precision::Street);
+        assert!((street.longitude - 24.141).abs() < 1e-9);
+        assert!(dataset.locate("Brīvības 1").is_none());
+    }
+
+    #[test]
+    fn parses_nominatim() {
+        let found = json!([{"lat": "56.9512", "lon": "24.1133", "addresstype": "building"}]);
+        let location = parse_nominatim(&found).unwrap().unwrap();
+        assert_eq!(location.precision, Precision::House);
+        assert_eq!(location.source, LocationSource::Nominatim);
+        assert!(parse_nominatim(&json!([])).unwrap().is_none());
+        assert!(parse_nominatim(&json!({"error": "Bad request"})).is_err());
+    }
+}
@@ -1,5 +1,21 @@
+pub mod analytics;
 pub mod apartment;
+pub mod area;
+pub mod bot;
+pub mod commute;
 pub mod config;
+pub mod contacts;
 pub mod db;
+pub mod description;
+pub mod diagnostics;
 pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod filter;
+pub mod geocode;
+pub mod metrics;
 pub mod page_handler;
+pub mod selectors;
+pub mod session;
+pub mod watchdog;
+pub mod web;
@@ -1,42 +1,40 @@
+use clap::{Args, Parser, Subcommand};
+use futures::StreamExt;
 use rentbot_sslv::{
+    analytics::{DealScore, MarketReport, WEEKS},
     apartment::*,
-    config::Config,
-    db::record::ApartmentRecrod,
+    bot,
+    commute::Router,
+    config::{Config, Settings},
+    db::{
+        cycle::CycleRecord,
+        listing::ListingRecord,
+        market::MarketSample,
+        price::PriceRecord,
+        profile::SearchProfile,
+        record::{ApartmentRecrod, RecordQuery},
+        stats::DbStats,
+        status::ListingStatus,
+    },
+    diagnostics::{FieldStatus, ParseReport, ParserHealth},
     error::SSError,
-    page_handler::{ApartmentPageRequest, SearchPageBuilder},
+    export::{Format, Listing},
+    fetch::{Fetcher, Validators},
+    filter::ApartmentFilter,
+    geocode::Geocoder,
+    metrics::Metrics,
+    page_handler::{target_distance, ApartmentPage, ApartmentPageRequest},
+    selectors::SelectorSpec,
+    session::SearchSession,
+    watchdog::Watchdog,
+    web,
 };
 use std::{
-    collections::{hash_map::IterMut, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    io::Write,
     sync::Arc,
 };
-use teloxide::prelude::*;
-
-fn _decode(
-    g: &'static str,
-    r: &'static str,
-    _k: u64,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let base = base64::decode(g.as_bytes())?;
-    let data_url = std::str::from_utf8(base.as_slice())?;
-    // println!("Decoded: {}", data);
-    let data_bytes = urlencoding::decode_binary(data_url.as_bytes()).into_owned();
-    let data_len = data_bytes.len();
-    let key_bytes = r.as_bytes();
-    let key_len = key_bytes.len();
-    let mut plain = String::new();
-    for i in 0..data_len {
-        let dbyte = data_bytes.get(i).ok_or(SSError::Index(i))?;
-        let kbyte = key_bytes.get(i % key_len).ok_or(SSError::Index(i))?;
-        let rbyte: u32 = (*dbyte as i32 - *kbyte as i32 + 14) as u32;
-        plain = format!(
-            "{}{}",
-            plain,
-            std::char::from_u32(rbyte as u32).ok_or(SSError::ConvertChar(rbyte))?
-        );
-    }
-    println!("res: {}", plain);
-    Ok(plain)
-}
+use teloxide::{prelude::*, types::MessageId};
 
 #[derive(Default, PartialEq, Copy, Clone, Debug)]
 enum ApartmentLifeCycle {
@@ -74,30 +72,36 @@ struct ApartmentCache {
 }
 
 impl ApartmentCache {
-    fn update(&mut self, apartments: Vec<Option<Apartment>>) {
-        for a in apartments {
-            match a {
-                Some(a) => {
-                    let key = a.id.clone();
-                    // println!("cache size is {}", self.apartments.len());
-                    if !self.apartments.contains_key(&key) {
-                        log::info!("Insert a key:{}", key);
-                        self.apartments.insert(key.clone(), a.into());
-                    } else {
-                        let value = self.apartments.get_mut(&key).unwrap();
-                        value.expired = false;
-                    }
-                    // println!("cache size is {}", self.apartments.len());
-                }
-                None => {}
+    /// Every entry expires unless it is seen again during the cycle
+    fn start_cycle(&mut self) {
+        for v in self.apartments.values_mut() {
+            v.expired = true;
+        }
+    }
+
+    fn update(&mut self, apartment: Apartment) -> &mut ApartmentWrapper {
+        match self.apartments.entry(apartment.id.clone()) {
+            Entry::Vacant(e) => {
+                log::info!("Insert a key:{}", e.key());
+                e.insert(apartment.into())
+            }
+            Entry::Occupied(e) => {
+                let wrapper = e.into_mut();
+                wrapper.expired = false;
+                wrapper
             }
         }
-        // Remove expired entires
-        self.apartments.retain(|_, v| !v.expired);
     }
 
-    fn iter(&mut self) -> IterMut<'_, String, ApartmentWrapper> {
-        self.apartments.iter_mut()
+    fn keep(&mut self, id: &str) {
+        if let Some(v) = self.apartments.get_mut(id) {
+            v.expired = false;
+        }
+    }
+
+    // Remove expired entires
+    fn finish_cycle(&mut self) {
+        self.apartments.retain(|_, v| !v.expired);
     }
 }
 
@@ -105,182 +109,945 @@ impl ApartmentCache {
 struct Telega {
     bot: Option<Bot>,
     chat: Option<String>,
+    admin_chat: Option<String>,
 }
 
 impl Telega {
-    fn new(bot: Option<Bot>, chat: Option<String>) -> Self {
-        Self { bot, chat }
+    fn new(bot: Option<Bot>, chat: Option<String>, admin_chat: Option<String>) -> Self {
+        Self {
+            bot,
+            chat,
+            admin_chat,
+        }
+    }
+
+    /// TELOXIDE_TOKEN, TELOXIDE_CHAT_ID and TELOXIDE_ADMIN_CHAT_ID
+    fn from_env() -> Self {
+        Self::new(
+            std::env::var("TELOXIDE_TOKEN")
+                .ok()
+                .map(|_| Bot::from_env()),
+            std::env::var("TELOXIDE_CHAT_ID").ok(),
+            std::env::var("TELOXIDE_ADMIN_CHAT_ID").ok(),
+        )
+    }
+
+    /// Does nothing unless both bot and chat are configured
+    async fn send(&self, msg: String) -> Result<(), SSError> {
+        self.send_reply(msg, None).await.map(|_| ())
     }
 
-    fn valid(&self) -> bool {
-        self.bot.is_some() && self.chat.is_some()
+    async fn send_reply(
+        &self,
+        msg: String,
+        reply_to: Option<MessageId>,
+    ) -> Result<Option<MessageId>, SSError> {
+        if let (Some(bot), Some(chat)) = (self.bot.as_ref(), self.chat.as_ref()) {
+            let mut request = bot.send_message(chat.clone(), msg);
+            if let Some(reply_to) = reply_to {
+                request = request.reply_to_message_id(reply_to);
+            }
+            let sent = request.await;
+            Metrics::notification("telegram", &sent);
+            Watchdog::component("telegram", &sent);
+            return Ok(Some(sent?.id));
+        }
+        Ok(None)
+    }
+
+    /// Service messages, does nothing unless the admin chat is configured
+    async fn send_admin(&self, msg: String) -> Result<(), SSError> {
+        if let (Some(bot), Some(chat)) = (self.bot.as_ref(), self.admin_chat.as_ref()) {
+            let sent = bot.send_message(chat.clone(), msg).await;
+            Metrics::notification("telegram_admin", &sent);
+            Watchdog::component("telegram", &sent);
+            sent?;
+        }
+        Ok(())
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    //*[@id="contacts_js"]
+struct PageOutcome {
+    request: ApartmentPageRequest,
+    report: Option<ParseReport>,
+    /// Validators of the fetched page
+    validators: Validators,
+    /// None when the page is not modified since the previous fetch
+    apartment: Result<Option<Apartment>, SSError>,
+}
 
-    // %8B%A2wTt%8E%7D%5Cv%A2wWq%7D%8F%94z%7Dg%8Fry%94%98s%7D%8DTmy%93%5C
+/// Rent bot searching for apartments on ss.lv
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    overrides: Overrides,
+    /// The daemon runs when no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    //<script async="" id="contacts_js" src="/js/ru/2023-03-27/6c92445aa0b94c2cee79f62a56ad8946ab403ff5d10abe33fc3d38cda40d9db5.js?d=%2FzN0xMdRYerwH%2FfH7J7TvWnbBhiKyUltnn%2B0T9AX3gs58DI7SaAwL85haA1shC57Ujem0oadnw2GfHWzHBZY0tkiplbZsihbyMKcojrD6DOa9wEx9HAYNtgpaJOaGNzc0p5d4NW8GTMtQx2H5%2FgyUUVi5PNql9xwiPPldHAi8B7QpQx%2BxeLf%2Fd6GY6Abw08iaWuOdCOm8l17hgg9Z0MkPVNmzXNVU3314TzUTXUk0ty5BoiqDNRLGnAoLHcVCYj%2FNMluVcA9bHRVf%2BvRUj57uUXfSPQSs0QtpQbTqsW2097F7l2b%2BnmRcfLd9CgFroE8yz9zqa5PrhpCQ5M4JK8jGwjakm5fD4yQxpNuSSoeI%2BsPy2uRnXZWQhSwYt6Qbba7G9miXPrHpf%2BdiiEPZaJ56I7K9L88nbN8U7TPkY4pNEyfnsDwjkwbOxW0MisCEriIThP%2FtZRFqCgEGXNT7ZSvFQ%3D%3D&amp;c=1"></script>
+/// Take precedence over the settings file
+#[derive(Args)]
+struct Overrides {
+    /// Settings file, RENTBOT_CONFIG or rentbot_sslv.toml by default
+    #[arg(long, global = true)]
+    config: Option<String>,
+    #[arg(long, global = true)]
+    database: Option<String>,
+    #[arg(long, global = true)]
+    selectors: Option<String>,
+    /// Filter page of the default search profile
+    #[arg(long, global = true)]
+    search_url: Option<String>,
+    /// Seconds between the crawl cycles
+    #[arg(long, global = true)]
+    interval: Option<u64>,
+    /// Ad pages fetched at once
+    #[arg(long, global = true)]
+    concurrency: Option<usize>,
+    /// Address of the web server, empty disables it
+    #[arg(long, global = true)]
+    web_bind: Option<String>,
+    /// Sends the search result rows before the ad pages are fetched
+    #[arg(long, global = true)]
+    fast_mode: bool,
+    /// Does not request the phone numbers
+    #[arg(long, global = true)]
+    no_contacts: bool,
+    #[arg(long, global = true, default_value = "trace")]
+    log_level: log::LevelFilter,
+}
 
-    // 1|JTg5JTlDJTdCVnYlOTIlN0JXdSVBMHhacyU4RSU3RVpwJTdCJTdEZXMlOEV6JTVFdXolOTMlOTNyeiU5MCU5MXh6eGU=|85730698	2|JTg3JTk2WCU4RXlqbyU5MHlqJTdEWWwlN0UlOTFabiU3RSU5Mm8=|82688600
+impl Overrides {
+    fn apply(&self, settings: &mut Settings) {
+        if let Some(database) = self.database.as_ref() {
+            settings.database = database.clone();
+        }
+        if let Some(selectors) = self.selectors.as_ref() {
+            settings.selectors = selectors.clone();
+        }
+        if let Some(url) = self.search_url.as_ref() {
+            settings.search.url = url.clone();
+        }
+        if let Some(interval) = self.interval {
+            settings.cycle.interval_secs = interval;
+        }
+        if let Some(concurrency) = self.concurrency {
+            settings.http.concurrency = concurrency;
+        }
+        if let Some(bind) = self.web_bind.as_ref() {
+            settings.web.bind = bind.clone();
+        }
+        if self.fast_mode {
+            settings.fast_mode = true;
+        }
+        if self.no_contacts {
+            settings.fetch_contacts = false;
+        }
+    }
+}
 
-    // key=key*6-47289+517
-    // -----------------------
-    // let phase1 = decode(
-    //     "JThCJUEyd1R0JThFJTdEJTVDdiVBMndXcSU3RCU4RiU5NHolN0RnJThGcnklOTQlOThzJTdEJThEVG15JTkzJTVD",
-    //     "77011366",
-    //     2,
-    // );
-    // decode("byU1QiU4MyU4NXglQTElOTlpJTk1JTk4", "K0dbVwzGrpLa-wRs2", 2);
+#[derive(Subcommand)]
+enum Command {
+    /// Crawls and notifies forever
+    Run,
+    /// Runs a single crawl cycle
+    Once,
+    /// Parses an ad page and prints the apartment as json
+    Parse {
+        /// Url of the ad or a saved html file
+        source: String,
+    },
+    /// Prints the listings matching the search profiles, nothing is sent or saved
+    Search {
+        /// Only this profile instead of the enabled ones
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Rent per m² by district and rooms, weekly trend and time on the search pages
+    Report {
+        /// Only the apartments of the profile
+        #[arg(long)]
+        profile: Option<String>,
+        /// Length of the weekly trend
+        #[arg(long, default_value_t = WEEKS)]
+        weeks: usize,
+        /// Prints json instead of the tables
+        #[arg(long)]
+        json: bool,
+    },
+    /// Inspects the database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Sends a sample message to the configured chats
+    TestNotify,
+}
 
-    // let client = reqwest::blocking::ClientBuilder::new()
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Sizes of the tables
+    Stats,
+    /// Prints the stored apartments, all or the matching ones
+    Export(ExportArgs),
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// csv, jsonl or geojson; by the extension of the output file or jsonl
+    #[arg(long, short)]
+    format: Option<Format>,
+    /// File to write to instead of stdout
+    #[arg(long, short)]
+    output: Option<String>,
+    /// Only the apartments of the profile
+    #[arg(long)]
+    profile: Option<String>,
+    /// new, interested, contacted or rejected
+    #[arg(long)]
+    status: Option<String>,
+    #[arg(long)]
+    price_min: Option<i64>,
+    #[arg(long)]
+    price_max: Option<i64>,
+    #[arg(long)]
+    area_min: Option<f64>,
+    #[arg(long)]
+    area_max: Option<f64>,
+    /// Part of the district name
+    #[arg(long)]
+    district: Option<String>,
+    /// Meters to the target location
+    #[arg(long)]
+    distance_max: Option<i64>,
+}
 
+#[tokio::main]
+async fn main() -> Result<(), SSError> {
+    let cli = Cli::parse();
     // simple_logging::log_to_file("rentsslv.log", log::LevelFilter::Trace)?;
     pretty_env_logger::formatted_timed_builder()
-        .filter(Some("rentbot_sslv"), log::LevelFilter::Trace)
+        .filter(Some("rentbot_sslv"), cli.overrides.log_level)
         .init();
-    let client = Arc::new(
-        reqwest::ClientBuilder::new()
-            .cookie_store(true)
-            .build()
-            .unwrap(),
-    );
-    let mut tlg = Telega::default(); //new(Some(bot), chat_id_opt);
-                                     // log::debug!("test");
-    tlg.chat = std::env::var("TELOXIDE_CHAT_ID").ok();
-    if std::env::var("TELOXIDE_TOKEN").is_ok() {
-        tlg.bot = Some(Bot::from_env());
-    }
-    // let bot = Bot::from_env();
-    // let chat_id_opt = std::env::var("TELOXIDE_CHAT_ID").ok();
-    let mut cache = ApartmentCache::default();
-    log::info!("telega: {:?}", tlg);
-    // log::info!("token => {}, chat id: {:?}", bot.token(), chat_id_opt);
-    if tlg.valid() {
-        tlg.bot
-            .as_ref()
-            .unwrap()
-            .send_message(tlg.chat.clone().unwrap(), "--==| Rebooted |==--")
-            .await
-            .unwrap();
+    let location = cli
+        .overrides
+        .config
+        .clone()
+        .unwrap_or_else(Config::location);
+    let mut settings = Settings::load(&location)?;
+    cli.overrides.apply(&mut settings);
+    Config::set(settings)?;
+    SelectorSpec::init()?;
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run().await,
+        Command::Once => Crawler::new(Telega::from_env())?.cycle().await,
+        Command::Parse { source } => parse(&source).await,
+        Command::Search { profile } => search(profile).await,
+        Command::Report {
+            profile,
+            weeks,
+            json,
+        } => {
+            let report = MarketReport::collect(profile.as_deref(), weeks)?;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&report)?),
+                false => println!("{}", report),
+            }
+            Ok(())
+        }
+        Command::Db {
+            command: DbCommand::Stats,
+        } => {
+            println!("{}", DbStats::collect()?);
+            Ok(())
+        }
+        Command::Db {
+            command: DbCommand::Export(args),
+        } => export(args),
+        Command::TestNotify => test_notify().await,
     }
-    loop {
-        let mut sp = SearchPageBuilder::new()
-            .url("https://www.ss.lv/ru/real-estate/flats/riga/today-2/hand_over/filter/")
-            .min_area(Config::area_low())
-            .max_price(Config::price_high())
-            .min_price(Config::price_low())
-            .build()?
-            .request(&client)
-            .await?
-            .parse()?;
-
-        let mut handlers = vec![];
-        // println!("{:?}", sp.apartments);
-        while let Ok(apartment_page_request) = sp.next() {
-            handlers.push(tokio::spawn(handle_page(
-                apartment_page_request,
-                client.clone(),
-            )));
-        }
-
-        let apartments: Vec<Option<Apartment>> = futures::future::join_all(handlers)
-            .await
-            .iter()
-            .map(|j| j.as_ref().unwrap().to_owned())
-            .collect();
-
-        cache.update(apartments);
-
-        // println!("cache size is {} before the loop", cache.apartments.len());
-        for entry in cache.iter() {
-            let mut record: ApartmentRecrod = entry.1.apartment.to_owned().into();
-            let mut skip = false;
-
-            let a = &entry.1.apartment;
-            if entry.1.lifecycle == ApartmentLifeCycle::Sent {
-                skip = true;
-            // } else if !a.elevator
-            //     && !a.description.clone().unwrap_or_default().elevator
-            //     && *a.floor.as_ref().unwrap_or_else(|| &(-1 as i64)) > 2
-            // {
-            //     log::trace!("Skip due to elevator conditions");
-            //     skip = true;
-            } else if ApartmentRecrod::select_one_exp_by(&record.id).is_ok() {
-                // Already has record for this id
-                entry.1.lifecycle = ApartmentLifeCycle::Sent;
-                skip = true;
-            } else {
-                log::warn!("Record id:{} is not there", record.id.value);
+}
+
+/// The daemon, never returns unless the setup fails
+async fn run() -> Result<(), SSError> {
+    let mut crawler = Crawler::new(Telega::from_env())?;
+    if !Config::web_bind().is_empty() {
+        tokio::spawn(async {
+            if let Err(e) = web::serve().await {
+                log::error!("Web server has stopped: {}", e);
             }
-            log::trace!(
-                "Skip:{}, Apartmend id:{}, lifecycle:{:?}",
-                skip,
-                entry.1.apartment.id,
-                entry.1.lifecycle
-            );
-            if !skip {
-                let brief = format!(
-                    "цена:{}, комн:{}, пл.:{} м2, дист:{} м, этаж:{}, лифт:{}, п.м.:{}, \nоп(л:{}, п:{}, б:{})",
-                    a.price,
-                    a.rooms,
-                    a.area,
-                    a.distance.unwrap_or(-1),
-                    a.floor.unwrap_or_default(),
-                    if a.elevator { "+" } else { "-" },
-                    if a.parking { "+" } else { "-" },
-                    if a.description.as_ref().unwrap_or(&ApartmentDescription::default()).elevator { "+" } else { "-" },
-                    if a.description.as_ref().unwrap_or(&ApartmentDescription::default()).park{ "+" } else { "-" },
-                    if a.description.as_ref().unwrap_or(&ApartmentDescription::default()).balkony{ "+" } else { "-" },
+        });
+    }
+    log::info!("telega: {:?}", crawler.tlg);
+    if let Some(bot) = crawler.tlg.bot.clone() {
+        let chats = [crawler.tlg.chat.clone(), crawler.tlg.admin_chat.clone()];
+        tokio::spawn(bot::listen(bot, chats.into_iter().flatten().collect()));
+    }
+    if let Err(e) = crawler.tlg.send("--==| Rebooted |==--".to_string()).await {
+        log::error!("Fail to send the reboot message: {}", e);
+    }
+    Watchdog::ready();
+    tokio::spawn(Watchdog::feed());
+    loop {
+        let delay = match crawler.cycle().await {
+            Ok(()) => Config::cycle_interval(),
+            Err(e) => {
+                let delay = retry_delay(&e);
+                log::error!(
+                    "Cycle has failed ({}), next attempt in {}s",
+                    e,
+                    delay.as_secs()
                 );
+                delay
+            }
+        };
+        Watchdog::sleeping(delay);
+        tokio::time::sleep(delay).await;
+    }
+}
 
-                record.brief.value = brief.clone();
-                if let Err(e) = record.insert() {
-                    log::error!("Fail to save record to the db: {}", e);
+/// State kept across the crawl cycles
+struct Crawler {
+    fetcher: Arc<Fetcher>,
+    sessions: HashMap<String, SearchSession>,
+    cache: ApartmentCache,
+    filter: ApartmentFilter,
+    health: ParserHealth,
+    // Summaries sent in the fast mode, waiting for the details
+    previews: HashMap<String, MessageId>,
+    geocoder: Option<Geocoder>,
+    router: Option<Router>,
+    tlg: Telega,
+}
+
+impl Crawler {
+    fn new(tlg: Telega) -> Result<Self, SSError> {
+        Ok(Self {
+            fetcher: Arc::new(Fetcher::from_config()?),
+            sessions: HashMap::new(),
+            cache: ApartmentCache::default(),
+            filter: ApartmentFilter::from_config(),
+            health: ParserHealth::default(),
+            previews: HashMap::new(),
+            geocoder: Geocoder::from_config()?,
+            router: Router::from_config()?,
+            tlg,
+        })
+    }
+
+    /// Searches every enabled profile, fetches the new pages and notifies;
+    /// fails if the profiles can not be loaded or every search has failed
+    async fn cycle(&mut self) -> Result<(), SSError> {
+        Watchdog::cycle_started();
+        let profiles = SearchProfile::select_enabled();
+        Watchdog::component("database", &profiles);
+        let profiles = profiles?;
+        let filter = self.filter.with_areas(&profiles);
+
+        let started = CycleRecord::now();
+        let cycle_timer = std::time::Instant::now();
+        let fetcher = self.fetcher.clone();
+        self.cache.start_cycle();
+        let mut requests: Vec<ApartmentPageRequest> = vec![];
+        let mut found = 0;
+        let mut failure = None;
+        for profile in profiles.iter() {
+            let name = profile.name.value.clone();
+            let sp = match self.session(profile) {
+                Some(session) => session.search(&fetcher).await,
+                None => continue,
+            };
+            Watchdog::component("search", &sp);
+            Watchdog::beat();
+            let mut sp = match sp {
+                Ok(sp) => sp,
+                Err(e) => {
+                    log::error!("Search '{}' has failed: {}", name, e);
+                    failure = Some(e);
+                    continue;
                 }
+            };
+            let profile_filter = self.filter.for_profile(profile);
+            let listings_found = Metrics::get().listings_found.with_label_values(&[&name]);
+            while let Ok(mut apr) = sp.next_request() {
+                found += 1;
+                listings_found.inc();
+                // Before the merge, the listing is recorded only for the
+                // profiles whose limits it meets
+                if let Some(reason) = profile_filter.summary_reject_reason(&apr.summary) {
+                    log::trace!("Skip id:{} due to the summary: {}", apr.id, reason);
+                    self.cache.keep(&apr.id);
+                    continue;
+                }
+                if let Some(known) = requests.iter_mut().find(|r| r.id == apr.id) {
+                    known.profiles.push(name.clone());
+                    // A profile which has not got the page yet can not take
+                    // "not modified" for an answer
+                    let fetched_for = |l: ListingRecord| l.fetched_for(&known.profiles);
+                    if known.known
+                        && !ListingRecord::select_by_id(&known.id)
+                            .ok()
+                            .flatten()
+                            .is_some_and(fetched_for)
+                    {
+                        known.validators = Validators::default();
+                    }
+                    continue;
+                }
+                apr.profiles.push(name.clone());
+                let Some(apr) = due(apr, &mut self.cache) else {
+                    continue;
+                };
+                // Sent before the pages were kept, e.g. before an upgrade
+                let sent = || {
+                    apr.profiles
+                        .iter()
+                        .any(|p| ApartmentRecrod::exists_for_profile(&apr.id, p).unwrap_or(false))
+                };
+                if Config::fast_mode()
+                    && !apr.known
+                    && !self.previews.contains_key(&apr.id)
+                    && !sent()
+                {
+                    match preview(&apr.summary, &self.tlg).await {
+                        Ok(Some(message)) => {
+                            self.previews.insert(apr.id.clone(), message);
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::error!("Fail to send the summary of id({}): {}", apr.id, e),
+                    }
+                }
+                requests.push(apr);
+            }
+        }
+        if let (Some(e), 0) = (failure, found) {
+            log::error!("Every search has failed");
+            return Err(e);
+        }
+
+        self.health.start_cycle();
+        log::info!("Fetching {} of {} page(s)", requests.len(), found);
+        let fetched = requests.len();
+        let mut failed = 0;
+        let requested: HashSet<String> = requests.iter().map(|r| r.id.clone()).collect();
 
-                let msg = format!("дата:{} \n{} \nссылка:{}", a.datetime, brief, a.url);
-                log::info!("Sending new apartment: id({}), url({})", a.id, a.url);
-                log::info!("Details: {:?}", brief);
-                // log::info!("Send message: {}", msg);
-                entry.1.lifecycle = if tlg.valid() {
-                    if tlg
-                        .bot
-                        .as_ref()
-                        .unwrap()
-                        .send_message(tlg.chat.clone().unwrap(), msg)
-                        .await
-                        .is_ok()
+        // Prices the new apartments are compared with
+        let market = MarketSample::select(None).unwrap_or_else(|e| {
+            log::error!("Fail to load the market samples: {}", e);
+            vec![]
+        });
+
+        // Pages are handled as soon as they are parsed, at most `concurrency`
+        // at once; the fetches go on in their own task while the parsed ones
+        // are routed and notified
+        let (sender, mut outcomes) = tokio::sync::mpsc::unbounded_channel();
+        let concurrency = Config::concurrency();
+        let pages_fetcher = fetcher.clone();
+        let fetching = tokio::spawn(async move {
+            let mut pages = futures::stream::iter(requests)
+                .map(|r| handle_page(r, pages_fetcher.clone()))
+                .buffer_unordered(concurrency);
+            while let Some(outcome) = pages.next().await {
+                if sender.send(outcome).is_err() {
+                    break;
+                }
+            }
+        });
+        while let Some(outcome) = outcomes.recv().await {
+            Watchdog::beat();
+            if let Some(report) = outcome.report.as_ref() {
+                self.health.record(report);
+                Metrics::parse_report(report);
+            }
+            let apr = &outcome.request;
+            match outcome.apartment {
+                Ok(Some(mut apartment)) => {
+                    locate(&mut apartment, self.geocoder.as_ref()).await;
+                    // Routing is the slow part, the apartments the other
+                    // conditions reject anyway are not routed
+                    if let (Some(router), Some(location)) =
+                        (self.router.as_ref(), apartment.location.as_ref())
                     {
-                        ApartmentLifeCycle::Sent
-                    } else {
-                        entry.1.lifecycle
+                        if worth_routing(&apartment, &filter, &apr.profiles) {
+                            apartment.commutes = router.commutes(location).await;
+                        }
                     }
-                } else {
-                    ApartmentLifeCycle::Sent
+                    let listing =
+                        ListingRecord::fetched(&apartment, &outcome.validators, &apr.profiles);
+                    let saved = listing.upsert();
+                    Watchdog::component("database", &saved);
+                    if let Err(e) = saved {
+                        log::error!("Fail to save page id:{}: {}", apr.id, e);
+                    }
+                    let price_eur = apartment.price_eur().map(i64::from);
+                    if let Err(e) = PriceRecord::record(&apr.id, &apartment.price, price_eur) {
+                        log::error!("Fail to save the price of id:{}: {}", apr.id, e);
+                    }
+                    let reply_to = self.previews.remove(&apr.id);
+                    let entry = self.cache.update(apartment);
+                    notify(entry, &filter, &market, &self.tlg, reply_to, &apr.profiles).await
+                }
+                Ok(None) => {
+                    if let Err(e) = ListingRecord::touch(&apr.id) {
+                        log::error!("Fail to save page id:{}: {}", apr.id, e);
+                    }
+                    self.cache.keep(&apr.id);
+                }
+                Err(e) => {
+                    failed += 1;
+                    log::error!("Fail to handle a page: {}", e)
                 }
             }
+        }
+        if let Err(e) = fetching.await {
+            log::error!("Fetching of the pages has failed: {}", e);
+        }
+        self.cache.finish_cycle();
+        // The pages which have failed are fetched again while listed, the
+        // previews of the gone ones are dropped
+        self.previews.retain(|id, _| requested.contains(id));
+
+        let cycle_health = self.health.finish_cycle();
+        log::info!(
+            "Parser health of {} page(s):\n{}",
+            cycle_health.pages,
+            cycle_health.report()
+        );
+        let mut alerts = vec![];
+        for alert in cycle_health.alerts.iter() {
+            let msg = format!(
+                "Поле '{}' распознано в {:.0}% страниц (обычно {:.0}%), возможно ss.lv изменил разметку",
+                alert.field,
+                alert.rate * 100.0,
+                alert.baseline * 100.0
+            );
+            log::warn!("{}", msg);
+            if let Err(e) = self.tlg.send_admin(msg.clone()).await {
+                log::error!("Fail to send the health alert: {}", e);
+            }
+            alerts.push(msg);
+        }
+        let mut cycle = CycleRecord::new();
+        cycle.started.value = started;
+        cycle.finished.value = CycleRecord::now();
+        cycle.profiles.value = profiles.len() as i64;
+        cycle.found.value = found as i64;
+        cycle.fetched.value = fetched as i64;
+        cycle.pages.value = cycle_health.pages as i64;
+        cycle.failed.value = failed;
+        cycle.report.value = cycle_health.report();
+        cycle.alerts.value = alerts.join("\n");
+        let saved = cycle.insert();
+        Watchdog::component("database", &saved);
+        if let Err(e) = saved {
+            log::error!("Fail to save the cycle: {}", e);
+        }
+        let circuit = match fetcher.is_open() {
+            true => Err("circuit breaker is open"),
+            false => Ok(()),
+        };
+        Watchdog::component("fetcher", &circuit);
+        Watchdog::cycle_finished();
+        Metrics::get()
+            .cycle_duration
+            .observe(cycle_timer.elapsed().as_secs_f64());
+        Metrics::get().cycles.inc();
+        Metrics::get()
+            .last_cycle
+            .set(chrono::Utc::now().timestamp());
+        Ok(())
+    }
+
+    /// Session of the profile, a changed profile gets a new one
+    fn session(&mut self, profile: &SearchProfile) -> Option<&mut SearchSession> {
+        let name = &profile.name.value;
+        let request = match profile.request() {
+            Ok(request) => request,
+            Err(e) => {
+                log::error!("Invalid search profile '{}': {}", name, e);
+                return None;
+            }
+        };
+        if self.sessions.get(name).map(|s| s.request()) != Some(&request) {
+            self.sessions
+                .insert(name.clone(), SearchSession::new(request));
+        }
+        self.sessions.get_mut(name)
+    }
+}
+
+/// `parse` command
+async fn parse(source: &str) -> Result<(), SSError> {
+    let (url, body) = match reqwest::Url::parse(source) {
+        Ok(url) if url.scheme().starts_with("http") => {
+            let fetcher = Fetcher::from_config()?;
+            let body = fetcher.text(|client| client.get(url.clone())).await?;
+            (url.to_string(), body)
+        }
+        _ => (source.to_string(), std::fs::read_to_string(source)?),
+    };
+    // Same as the ads are named: .../abcde.html
+    let id = std::path::Path::new(&url)
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let page = ApartmentPage::new(url, id, scraper::Html::parse_document(&body));
+    let (report, apartment) = page.parse_with_report();
+    for (field, status) in report.fields.iter() {
+        if *status != FieldStatus::Ok {
+            log::warn!("Field '{}': {:?}", field, status);
+        }
+    }
+    let mut apartment = apartment?;
+    locate(&mut apartment, Geocoder::from_config()?.as_ref()).await;
+    if let (Some(router), Some(location)) = (Router::from_config()?, apartment.location.as_ref()) {
+        apartment.commutes = router.commutes(location).await;
+    }
+    println!("{}", serde_json::to_string_pretty(&apartment)?);
+    Ok(())
+}
+
+/// Geocodes the address when the ad has no map link
+async fn locate(apartment: &mut Apartment, geocoder: Option<&Geocoder>) {
+    if let (None, Some(geocoder)) = (apartment.location.as_ref(), geocoder) {
+        apartment.location = geocoder
+            .locate(&apartment.city, &apartment.district, &apartment.address)
+            .await;
+        apartment.distance = apartment.location.as_ref().map(target_distance);
+        match apartment.location.as_ref() {
+            Some(l) => log::debug!(
+                "Geocoded id:{} by {} to {},{} ({})",
+                apartment.id,
+                l.source.as_str(),
+                l.latitude,
+                l.longitude,
+                l.precision.as_str()
+            ),
+            None => log::warn!(
+                "No coordinates of id:{} '{}'",
+                apartment.id,
+                apartment.address
+            ),
+        }
+    }
+}
+
+/// The apartment passes the conditions other than the commute limits, the
+/// same as `notify` checks them before the commutes are known
+fn worth_routing(a: &Apartment, filter: &ApartmentFilter, profiles: &[String]) -> bool {
+    let outside = !profiles.is_empty()
+        && profiles
+            .iter()
+            .all(|p| filter.area_reject_reason(a, p).is_some());
+    filter.reject_reason(a).is_none() && !outside
+}
+
+/// `search` command
+async fn search(only: Option<String>) -> Result<(), SSError> {
+    let profiles = match only {
+        Some(name) => vec![SearchProfile::select_by_name(&name)?
+            .ok_or(SSError::NotFound(format!("profile '{}'", name)))?],
+        // Same as select_enabled, without saving the default profile
+        None => match SearchProfile::select_all()? {
+            profiles if profiles.is_empty() => vec![SearchProfile::from_config()],
+            profiles => profiles.into_iter().filter(|p| p.enabled.value).collect(),
+        },
+    };
+    let fetcher = Fetcher::from_config()?;
+    let filter = ApartmentFilter::from_config();
+    for profile in profiles.iter() {
+        let filter = filter.for_profile(profile);
+        let mut sp = SearchSession::new(profile.request()?)
+            .search(&fetcher)
+            .await?;
+        println!("# {}", profile.name.value);
+        while let Ok(apr) = sp.next_request() {
+            if let Some(reason) = filter.summary_reject_reason(&apr.summary) {
+                log::info!("Skip id:{} due to the summary: {}", apr.id, reason);
+                continue;
+            }
+            println!("{}\n  {}", apr.summary.brief(), apr.href);
+        }
+    }
+    Ok(())
+}
 
-            entry.1.expired = true;
-            // println!("send");
+/// `db export` command
+fn export(args: ExportArgs) -> Result<(), SSError> {
+    let format = args
+        .format
+        .or_else(|| args.output.as_deref().and_then(Format::from_path))
+        .unwrap_or_default();
+    let query = RecordQuery {
+        profile: args.profile,
+        price_min: args.price_min,
+        price_max: args.price_max,
+        area_min: args.area_min,
+        area_max: args.area_max,
+        district: args.district,
+        distance_max: args.distance_max,
+        status: args
+            .status
+            .as_deref()
+            .map(ListingStatus::parse)
+            .transpose()?,
+        ..Default::default()
+    };
+    let listings = Listing::select(&query)?;
+    match args.output {
+        Some(path) => {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            format.write(&listings, &mut file)?;
+            file.flush()?;
         }
-        // println!("cache size is {} after the loop", cache.apartments.len());
-        // println!("sleep");
-        tokio::time::sleep(tokio::time::Duration::from_secs(60 * 10)).await;
+        None => format.write(&listings, &mut std::io::stdout().lock())?,
     }
-    // return Ok(());
+    log::info!(
+        "Exported {} apartment(s) as {}",
+        listings.len(),
+        format.as_str()
+    );
+    Ok(())
 }
 
-async fn handle_page(apr: ApartmentPageRequest, client: Arc<reqwest::Client>) -> Option<Apartment> {
-    let page_res = apr.request(client).await.unwrap().parse();
-    match page_res {
-        Ok(page) => Some(page),
+/// `test-notify` command
+async fn test_notify() -> Result<(), SSError> {
+    let tlg = Telega::from_env();
+    if tlg.bot.is_none() {
+        return Err(SSError::Config("TELOXIDE_TOKEN is not set".into()));
+    }
+    let msg = format!(
+        "Тестовое сообщение rentbot_sslv, {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    match tlg.chat {
+        Some(_) => {
+            tlg.send(msg.clone()).await?;
+            println!("Sent to the chat");
+        }
+        None => println!("TELOXIDE_CHAT_ID is not set"),
+    }
+    match tlg.admin_chat {
+        Some(_) => {
+            tlg.send_admin(msg).await?;
+            println!("Sent to the admin chat");
+        }
+        None => println!("TELOXIDE_ADMIN_CHAT_ID is not set"),
+    }
+    Ok(())
+}
+
+/// Next attempt of a failed search is sooner than the regular cycle, but not
+/// before the circuit closes
+fn retry_delay(e: &SSError) -> std::time::Duration {
+    match e {
+        SSError::CircuitOpen(until) => (*until - chrono::Local::now().naive_local())
+            .to_std()
+            .unwrap_or_default()
+            .max(Config::cycle_retry_interval()),
+        _ => Config::cycle_retry_interval(),
+    }
+}
+
+/// Sends the summary of a new listing before its page is fetched
+async fn preview(summary: &ListingSummary, tlg: &Telega) -> Result<Option<MessageId>, SSError> {
+    let text: String = summary.text.chars().take(200).collect();
+    let msg = format!("{} \n{} \nссылка:{}", summary.brief(), text, summary.url);
+    log::info!("Sending summary: id({}), url({})", summary.id, summary.url);
+    tlg.send_reply(msg, None).await
+}
+
+/// Records the apartment for the profiles which have found it and sends it
+/// unless it is filtered out or already sent; the details of the fast mode
+/// summary are sent as a reply to it
+async fn notify(
+    entry: &mut ApartmentWrapper,
+    filter: &ApartmentFilter,
+    market: &[MarketSample],
+    tlg: &Telega,
+    reply_to: Option<MessageId>,
+    profiles: &[String],
+) {
+    let mut record: ApartmentRecrod = entry.apartment.to_owned().into();
+
+    let a = &entry.apartment;
+    let score = DealScore::of(&MarketSample::from(a), market);
+    // Recorded for the profiles the apartment is in the areas of
+    let mut outside = None;
+    let profiles: Vec<&String> = profiles
+        .iter()
+        .filter(|p| match filter.area_reject_reason(a, p) {
+            Some(reason) => {
+                log::trace!("Skip id:{} for '{}': {}", a.id, p, reason);
+                outside = Some(reason);
+                false
+            }
+            None => true,
+        })
+        .collect();
+    let rejected = filter
+        .reject_reason(a)
+        .or_else(|| outside.filter(|_| profiles.is_empty()))
+        .or_else(|| filter.deal_reject_reason(score.as_ref()));
+    if let Some(reason) = rejected {
+        log::trace!("Skip due to filter conditions: {}", reason);
+        if reply_to.is_some() {
+            let msg = format!("не подходит: {}", reason);
+            if let Err(e) = tlg.send_reply(msg, reply_to).await {
+                log::error!("Fail to send the rejection of id({}): {}", a.id, e);
+            }
+        }
+        return;
+    }
+    // Already has record for this id
+    let sent = entry.lifecycle == ApartmentLifeCycle::Sent
+        || ApartmentRecrod::select_one_exp_by(&record.id).is_ok();
+    log::trace!(
+        "Sent:{}, Apartmend id:{}, lifecycle:{:?}",
+        sent,
+        entry.apartment.id,
+        entry.lifecycle
+    );
+    let brief = format!(
+        "цена:{}, комн:{}, пл.:{} м2, дист:{}, этаж:{}, лифт:{}, п.м.:{}, {}, \nоп({})",
+        a.price,
+        a.rooms,
+        a.area,
+        a.distance_brief(),
+        a.floor_brief(),
+        if a.elevator { "+" } else { "-" },
+        if a.parking { "+" } else { "-" },
+        a.seller_brief(),
+        a.description.clone().unwrap_or_default().brief(),
+    );
+
+    record.brief.value = brief.clone();
+    for profile in profiles {
+        match ApartmentRecrod::exists_for_profile(&a.id, profile) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => log::error!("Fail to look up the record: {}", e),
+        }
+        record.profile.value = profile.to_string();
+        if let Err(e) = record.insert() {
+            log::error!("Fail to save record to the db: {}", e);
+        }
+    }
+    if sent {
+        entry.lifecycle = ApartmentLifeCycle::Sent;
+        return;
+    }
+
+    let commutes = match a.commutes.is_empty() {
+        true => String::new(),
+        false => format!(
+            "дорога: {} \n",
+            a.commutes
+                .iter()
+                .map(|c| c.brief())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let msg = format!(
+        "дата:{} \n{} \n{} \n{}тел:{} \nссылка:{}",
+        a.datetime,
+        brief,
+        score.map_or(
+            "оценка: мало похожих объявлений".to_string(),
+            |s| s.message()
+        ),
+        commutes,
+        if a.phones.is_empty() {
+            "-".to_string()
+        } else {
+            a.phones.join(", ")
+        },
+        a.url
+    );
+    log::info!("Sending new apartment: id({}), url({})", a.id, a.url);
+    log::info!("Details: {:?}", brief);
+    // log::info!("Send message: {}", msg);
+    match tlg.send_reply(msg, reply_to).await {
+        Ok(_) => entry.lifecycle = ApartmentLifeCycle::Sent,
+        Err(e) => log::error!("Fail to send apartment id({}): {}", a.id, e),
+    }
+}
+
+/// Known pages are skipped until it is time to revalidate them, unless a
+/// profile has not got them yet
+fn due(mut apr: ApartmentPageRequest, cache: &mut ApartmentCache) -> Option<ApartmentPageRequest> {
+    match ListingRecord::select_by_id(&apr.id) {
+        Ok(Some(listing)) => {
+            apr.known = true;
+            if !listing.fetched_for(&apr.profiles) {
+                // Not "not modified" for the profile which has never seen it
+                log::trace!("Fetch known page id:{} for a new profile", apr.id);
+                return Some(apr);
+            }
+            let fresh = listing
+                .age()
+                .and_then(|age| age.to_std().ok())
+                .is_some_and(|age| age < Config::revalidate_after());
+            if fresh {
+                log::trace!("Skip known page id:{}", apr.id);
+                if let Err(e) = ListingRecord::seen(&apr.id) {
+                    log::error!("Fail to save page id:{}: {}", apr.id, e);
+                }
+                cache.keep(&apr.id);
+                return None;
+            }
+            apr.validators = listing.validators();
+            Some(apr)
+        }
+        Ok(None) => Some(apr),
         Err(e) => {
-            log::error!("Error during parse of a page '{}': {}", apr.href, e);
+            log::error!("Fail to look up page id:{}: {}", apr.id, e);
+            Some(apr)
+        }
+    }
+}
+
+async fn handle_page(apr: ApartmentPageRequest, fetcher: Arc<Fetcher>) -> PageOutcome {
+    // The page is not Send, so it has to be gone before the contacts are requested
+    let (contacts, report, validators, apartment) = {
+        let page = match apr.request(fetcher.clone()).await {
+            Ok(Some(page)) => page,
+            result => {
+                return PageOutcome {
+                    request: apr,
+                    report: None,
+                    validators: Validators::default(),
+                    apartment: result.map(|_| None),
+                }
+            }
+        };
+        let contacts = if Config::fetch_contacts() {
+            page.parse_contacts_request()
+                .map_err(|e| log::warn!("No contacts script in '{}': {}", apr.href, e))
+                .ok()
+        } else {
             None
+        };
+        let validators = page.validators.clone();
+        let (report, apartment) = page.parse_with_report();
+        (contacts, report, validators, apartment)
+    };
+    let apartment = match (apartment, contacts) {
+        (Ok(mut apartment), Some(contacts)) => {
+            match contacts.request(fetcher).await {
+                Ok(phones) => apartment.phones = phones,
+                Err(e) => log::warn!("Fail to get contacts of '{}': {}", apr.href, e),
+            }
+            Ok(apartment)
         }
+        (apartment, _) => apartment,
+    };
+    PageOutcome {
+        request: apr,
+        report: Some(report),
+        validators,
+        apartment: apartment.map(Some),
     }
 }
@@ -0,0 +1,182 @@
+use std::{sync::OnceLock, time::Duration};
+
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::diagnostics::{FieldStatus, ParseReport};
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Counters and histograms served at /metrics
+pub struct Metrics {
+    registry: Registry,
+    pub cycles: IntCounter,
+    pub cycle_duration: Histogram,
+    /// Unix time the last cycle has finished
+    pub last_cycle: IntGauge,
+    pub search_page_bytes: Histogram,
+    /// Listings on the search pages, by profile
+    pub listings_found: IntCounterVec,
+    /// Ad page request including the retries
+    pub detail_fetch: Histogram,
+    /// Answers of every request attempt, by status code or "error"
+    pub http_responses: IntCounterVec,
+    /// Fields of the ad pages which are missing or malformed
+    pub parse_failures: IntCounterVec,
+    /// By sink and result, "sent" or "failed"
+    pub notifications: IntCounterVec,
+    /// Statement execution time by its kind: select, insert, ...
+    pub db_query: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("rentbot".into()), None)?;
+        let metrics = Self {
+            cycles: IntCounter::new("cycles_total", "Finished crawl cycles")?,
+            cycle_duration: Histogram::with_opts(
+                HistogramOpts::new("cycle_duration_seconds", "Duration of the crawl cycles")
+                    .buckets(exponential_buckets(1.0, 2.0, 12)?),
+            )?,
+            last_cycle: IntGauge::new(
+                "last_cycle_timestamp_seconds",
+                "Unix time the last crawl cycle has finished",
+            )?,
+            search_page_bytes: Histogram::with_opts(
+                HistogramOpts::new("search_page_bytes", "Size of the search result pages")
+                    .buckets(exponential_buckets(16_384.0, 2.0, 8)?),
+            )?,
+            listings_found: IntCounterVec::new(
+                Opts::new("listings_found_total", "Listings on the search pages"),
+                &["profile"],
+            )?,
+            detail_fetch: Histogram::with_opts(
+                HistogramOpts::new(
+                    "detail_fetch_seconds",
+                    "Latency of the ad page requests including the retries",
+                )
+                .buckets(exponential_buckets(0.1, 2.0, 10)?),
+            )?,
+            http_responses: IntCounterVec::new(
+                Opts::new("http_responses_total", "Answers of the request attempts"),
+                &["status"],
+            )?,
+            parse_failures: IntCounterVec::new(
+                Opts::new(
+                    "parse_failures_total",
+                    "Fields of the ad pages failed to parse",
+                ),
+                &["field", "kind"],
+            )?,
+            notifications: IntCounterVec::new(
+                Opts::new("notifications_total", "Notifications by sink and result"),
+                &["sink", "result"],
+            )?,
+            db_query: HistogramVec::new(
+                HistogramOpts::new("db_query_seconds", "Execution time of the db statements")
+                    .buckets(exponential_buckets(0.0001, 4.0, 8)?),
+                &["op"],
+            )?,
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.cycles.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.cycle_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.last_cycle.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.search_page_bytes.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.listings_found.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.detail_fetch.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.http_responses.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.parse_failures.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.notifications.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.db_query.clone()))?;
+        Ok(metrics)
+    }
+
+    pub fn get() -> &'static Self {
+        METRICS.get_or_init(|| Self::new().expect("metric names are valid and unique"))
+    }
+
+    /// Text exposition format
+    pub fn render() -> String {
+        let mut buffer = vec![];
+        if let Err(e) = TextEncoder::new().encode(&Self::get().registry.gather(), &mut buffer) {
+            log::error!("Fail to encode the metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+
+    pub fn parse_report(report: &ParseReport) {
+        for (field, status) in report.fields.iter() {
+            let kind = match status {
+                FieldStatus::Ok => continue,
+                FieldStatus::Missing => "missing",
+                FieldStatus::Malformed(_) => "malformed",
+            };
+            Self::get()
+                .parse_failures
+                .with_label_values(&[field, kind])
+                .inc();
+        }
+    }
+
+    pub fn notification<T, E>(sink: &str, result: &Result<T, E>) {
+        let result = if result.is_ok() { "sent" } else { "failed" };
+        Self::get()
+            .notifications
+            .with_label_values(&[sink, result])
+            .inc();
+    }
+
+    /// Profiler of the db connections
+    pub fn db_statement(sql: &str, duration: Duration) {
+        let op = sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        Self::get()
+            .db_query
+            .with_label_values(&[&op])
+            .observe(duration.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_parse_failures() {
+        let mut report = ParseReport::new("https://www.ss.lv/msg/1.html");
+        report.fields.insert("price", FieldStatus::Ok);
+        report.fields.insert("floor", FieldStatus::Missing);
+        Metrics::parse_report(&report);
+        Metrics::db_statement("SELECT * FROM record", Duration::from_millis(1));
+        let text = Metrics::render();
+        assert!(text.contains(r#"rentbot_parse_failures_total{field="floor",kind="missing"}"#));
+        assert!(!text.contains(r#"field="price""#));
+        assert!(text.contains(r#"rentbot_db_query_seconds_count{op="select"}"#));
+    }
+}
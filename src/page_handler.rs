@@ -1,26 +1,43 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
 
 use crate::{
-    apartment::{Apartment, ApartmentBuilder, ApartmentDescription, Location},
+    apartment::{
+        Apartment, ApartmentBuilder, ApartmentDescription, ListingSummary, Location,
+        LocationSource, Precision, SellerInfo,
+    },
+    contacts::ContactsRequest,
+    description::DescriptionAnalyzer,
+    diagnostics::ParseReport,
     error::SSError,
+    fetch::{Fetched, Fetcher, Validators},
+    metrics::Metrics,
+    selectors::{SelectorSpec, Value},
 };
 use regex::Regex;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html};
 
 // POST Requests Arguments
 static PA_PRICE_LOW: &str = "topt[8][min]";
 static PA_PRICE_HIGH: &str = "topt[8][max]";
 static PA_AREA_LOW: &str = "topt[3][min]";
-const EARTH_RADIUS: f64 = 6_371_000 as f64;
+/// "district<br>street" of the search results row
+static LINE_BREAK: OnceLock<Regex> = OnceLock::new();
+const EARTH_RADIUS: f64 = 6_371_000_f64;
 const TARGET_LOCATION: Location = Location {
     latitude: 56.9585757,
     longitude: 24.1257553,
+    source: LocationSource::Map,
+    precision: Precision::Exact,
 };
 
 pub struct ApartmentPage {
     pub url: String,
     pub id: String,
     pub page: Html,
+    pub validators: Validators,
     // apartment_details: Apartment,
 }
 
@@ -30,183 +47,138 @@ impl ApartmentPage {
             url,
             id,
             page,
+            validators: Validators::default(),
             // apartment_details: Apartment::default(),
         }
     }
 
-    fn parse_attr(
-        &self,
-        selector_str: &str,
-        attr: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let selector = Selector::parse(&selector_str).unwrap();
-        let value = self
-            .page
-            .select(&selector)
-            .next()
-            .ok_or(Box::new(SSError::Selector(selector_str.to_string())))?;
-        // println!("{:?}", value);
-        let attr_value = value
-            .value()
-            .attr(attr)
-            .ok_or(Box::new(SSError::Selector(selector_str.to_string())))?
-            .to_string();
-        Ok(attr_value)
-    }
-
-    fn parse_string(&self, selector_str: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // println!("{:?}", selector_str);
-        let selector = Selector::parse(&selector_str).unwrap();
-        let value = self
-            .page
-            .select(&selector)
-            .next()
-            .ok_or(Box::new(SSError::Selector(selector_str.to_string())))?
-            .inner_html();
-        // println!("parsed: {:?}", value);
-        // println!("trimmed str: {:?}", value.trim());
-        Ok(value.trim().to_string())
+    fn extract(&self, field: &str) -> Result<Value, SSError> {
+        SelectorSpec::global().extract(&self.page, field)
     }
 
-    fn parse_f64(&self, selector_str: &str) -> Result<f64, Box<dyn std::error::Error>> {
-        let selector = Selector::parse(&selector_str).unwrap();
-        let value = self
-            .page
-            .select(&selector)
-            .next()
-            .ok_or(Box::new(SSError::Selector(selector_str.to_string())))?
-            .inner_html();
-        // println!("parsed: {:?}", value);
-        // println!("trimmed f64: {:?}", value.trim());
-        let v: f64 = value.as_str().trim().split(' ').next().unwrap().parse()?;
-        // println!("v: {:?}", value);
-        Ok(v)
+    pub fn parse_price(&self) -> Result<String, SSError> {
+        Ok(self.extract("price")?.into_string())
     }
 
-    pub fn parse_price(&self) -> Result<String, Box<dyn std::error::Error>> {
-        self.parse_string("#tdo_8")
+    pub fn parse_area(&self) -> Result<f64, SSError> {
+        self.extract("area")?.as_f64("area")
     }
 
-    pub fn parse_area(&self) -> Result<f64, Box<dyn std::error::Error>> {
-        self.parse_f64("#tdo_3")
+    pub fn parse_rooms(&self) -> Result<f64, SSError> {
+        self.extract("rooms")?.as_f64("rooms")
     }
 
-    pub fn parse_rooms(&self) -> Result<f64, Box<dyn std::error::Error>> {
-        self.parse_f64("#tdo_1")
+    pub fn parse_parking(&self) -> Result<bool, SSError> {
+        self.extract("parking")?.as_bool("parking")
+    }
+    pub fn parse_description(&self) -> Result<ApartmentDescription, SSError> {
+        Ok(DescriptionAnalyzer::new(&self.parse_description_text()?).analyze())
     }
 
-    pub fn parse_parking(&self) -> Result<bool, Box<dyn std::error::Error>> {
-        let parking = self.parse_string("#tdo_1734")?.to_lowercase();
-        let found = Regex::new(r#"парков"#)?.is_match(&parking);
-        Ok(found)
+    pub fn parse_description_text(&self) -> Result<String, SSError> {
+        Ok(self
+            .extract("description")?
+            .into_string()
+            .trim()
+            .to_string())
     }
-    pub fn parse_description_p_e(
-        &self,
-    ) -> Result<ApartmentDescription, Box<dyn std::error::Error>> {
-        let descr = self.parse_string("#msg_div_msg")?.to_lowercase();
-        let park_found = Regex::new(r#" парк| park"#)?.is_match(&descr);
-        let balkony_found = Regex::new(r#" балкон| терасса| balkony| terrace"#)?.is_match(&descr);
-        let elevator_found = Regex::new(r#" лифт| lift| elevator"#)?.is_match(&descr);
-        Ok(ApartmentDescription::new(
-            park_found,
-            elevator_found,
-            balkony_found,
-        ))
-        // #msg_div_msg
+
+    pub fn parse_seller(&self) -> Result<SellerInfo, SSError> {
+        let spec = SelectorSpec::global().seller.matchers()?;
+        let mut seller = SellerInfo::default();
+        for label in self.page.select(&spec.label) {
+            let label_text = label.text().collect::<String>();
+            let value = label
+                .next_siblings()
+                .find_map(ElementRef::wrap)
+                .map(|v| v.text().collect::<String>().trim().to_string())
+                .filter(|v| !v.is_empty());
+            if spec.company_labels.is_match(&label_text) {
+                seller.company = value;
+                seller.is_agency = true;
+            } else if spec.agency_labels.is_match(&label_text) {
+                seller.is_agency = true;
+            }
+        }
+        seller.has_phone = self.page.select(&spec.phone).next().is_some();
+        Ok(seller)
     }
 
-    pub fn parse_floor_f_e(&self) -> Result<(i64, bool), Box<dyn std::error::Error>> {
-        let floor_line = self.parse_string("#tdo_4")?.to_lowercase();
-        let elevator_found = Regex::new(r#"лифт"#)?.is_match(&floor_line);
-        let floor = floor_line
-            .split('/')
-            .next()
-            .ok_or(Box::new(SSError::Parse(format!(
-                "Fail to parse floor out of {}",
-                floor_line
-            ))))?
-            .parse()?;
-
-        Ok((floor, elevator_found))
-    }
-    pub fn parse_location(&self) -> Result<Location, Box<dyn std::error::Error>> {
-        let map = self.parse_attr("#mnu_map", "onclick")?.to_lowercase();
-        // Get coordinates out of 'onclick' attribute
-        let re = Regex::new(r#"&c=([[:digit:]]+\.[[:digit:]]+), ([[:digit:]]+\.[[:digit:]]+)"#)?;
-        let captures = re.captures(&map).ok_or(Box::new(SSError::Parse(format!(
-            "Fail to parse coordinates out of {}",
-            map
-        ))))?;
-        let lat = captures
-            .get(1)
-            .ok_or(Box::new(SSError::Parse(
-                "Fail to parse latitude".to_string(),
-            )))?
-            .as_str()
-            .parse()?;
-        let lon = captures
-            .get(2)
-            .ok_or(Box::new(SSError::Parse(
-                "Fail to parse longitude".to_string(),
-            )))?
-            .as_str()
-            .parse()?;
-
-        // println!("{:?}", captures);
-        Ok(Location {
-            longitude: lon,
-            latitude: lat,
-        })
+    pub fn parse_contacts_request(&self) -> Result<ContactsRequest, SSError> {
+        let src = self.extract("contacts_script")?.into_string();
+        ContactsRequest::from_page(&self.page, &self.url, &src)
+    }
+
+    pub fn parse_floor_f_t_e(&self) -> Result<(i64, Option<i64>, bool), SSError> {
+        let floor = self.extract("floor")?.as_i64("floor")?;
+        let total_floors = self
+            .extract("total_floors")
+            .and_then(|t| t.as_i64("total_floors"))
+            .ok();
+        let elevator_found = self
+            .extract("floor_elevator")
+            .and_then(|e| e.as_bool("floor_elevator"))
+            .unwrap_or(false);
+
+        Ok((floor, total_floors, elevator_found))
+    }
+    pub fn parse_location(&self) -> Result<Location, SSError> {
+        let lat = self.extract("latitude")?.as_f64("latitude")?;
+        let lon = self.extract("longitude")?.as_f64("longitude")?;
+        Ok(Location::exact(lat, lon))
     }
 
-    pub fn parse_city(&self) -> Result<String, Box<dyn std::error::Error>> {
-        let city = self.parse_string("#tdo_20 > b")?;
-        Ok(city)
-    }
-    pub fn parse_district(&self) -> Result<String, Box<dyn std::error::Error>> {
-        let district = self.parse_string("#tdo_856 > b")?;
-        Ok(district)
-    }
-    pub fn parse_address(&self) -> Result<String, Box<dyn std::error::Error>> {
-        let address = self.parse_string("#tdo_11 > b")?;
-        Ok(address)
-    }
-    pub fn parse_datetime(&self) -> Result<chrono::NaiveDateTime, Box<dyn std::error::Error>> {
-        let datetime_parsed = self.parse_string("td.msg_footer:nth-child(2)")?;
-        let datetime_str =
-            Regex::new("([[:digit:]]+\\.[[:digit:]]+\\.[[:digit:]]+ [[:digit:]]+:[[:digit:]]+)")?
-                .captures(datetime_parsed.as_str())
-                .ok_or(Box::new(SSError::Parse(format!(
-                    "Fail to parse datetime: {}",
-                    datetime_parsed
-                ))))?
-                .get(1)
-                .ok_or(Box::new(SSError::Parse(format!(
-                    "Fail to get match of datetime: {}",
-                    datetime_parsed
-                ))))?
-                .as_str();
-        // println!("{:?}", datetime_str);
-        Ok(chrono::NaiveDateTime::parse_from_str(
-            datetime_str,
-            "%d.%m.%Y %H:%M",
-        )?)
-    }
-
-    pub fn parse(self) -> Result<Apartment, Box<dyn std::error::Error>> {
+    pub fn parse_city(&self) -> Result<String, SSError> {
+        Ok(self.extract("city")?.into_string())
+    }
+    pub fn parse_district(&self) -> Result<String, SSError> {
+        Ok(self.extract("district")?.into_string())
+    }
+    pub fn parse_address(&self) -> Result<String, SSError> {
+        Ok(self.extract("address")?.into_string())
+    }
+    pub fn parse_datetime(&self) -> Result<chrono::NaiveDateTime, SSError> {
+        self.extract("datetime")?.as_datetime("datetime")
+    }
+
+    pub fn parse(self) -> Result<Apartment, SSError> {
+        self.parse_with_report().1
+    }
+
+    /// Parses the page and reports the status of every field
+    pub fn parse_with_report(self) -> (ParseReport, Result<Apartment, SSError>) {
+        let mut report = ParseReport::new(&self.url);
+        let apartment = self.parse_tracked(&mut report);
+        (report, apartment)
+    }
+
+    fn parse_tracked(self, report: &mut ParseReport) -> Result<Apartment, SSError> {
         // println!("{:?}", self.page);
-        let city = self.parse_city().unwrap_or_default();
+        let city = report.track("city", self.parse_city()).unwrap_or_default();
         // return Ok(());
-        let district = self.parse_district().unwrap_or_default();
-        let address = self.parse_address().unwrap_or_default();
-        let price = self.parse_price().unwrap_or_default();
-        let area = self.parse_area().unwrap_or_default();
-        let rooms = self.parse_rooms().unwrap_or_default() as u64;
-        let parking = self.parse_parking().unwrap_or(false);
-        let descr = self.parse_description_p_e().ok();
-        let floor = self.parse_floor_f_e().ok();
-        println!(
-            "city: {}\ndistrict: {}\naddress: {}\nprice: {}\nrooms: {}\narea: {} \nfloor: {:?}\nparking: {:?}",
+        let district = report
+            .track("district", self.parse_district())
+            .unwrap_or_default();
+        let address = report
+            .track("address", self.parse_address())
+            .unwrap_or_default();
+        let price = report
+            .track("price", self.parse_price())
+            .unwrap_or_default();
+        let area = report.track("area", self.parse_area()).unwrap_or_default();
+        let rooms = report
+            .track("rooms", self.parse_rooms())
+            .unwrap_or_default() as u64;
+        let parking = report
+            .track("parking", self.parse_parking())
+            .unwrap_or(false);
+        let descr = report.track("description", self.parse_description()).ok();
+        let descr_text = self.parse_description_text().unwrap_or_default();
+        let floor = report.track("floor", self.parse_floor_f_t_e()).ok();
+        let seller = report.track("seller", self.parse_seller()).ok();
+        log::debug!(
+            "Parsed id:{} city: {}, district: {}, address: {}, price: {}, rooms: {}, area: {}, floor: {:?}, parking: {:?}",
+            self.id,
             city,
             district,
             address,
@@ -216,28 +188,21 @@ impl ApartmentPage {
             floor,
             parking,
         );
-        let loc = self.parse_location().ok();
-        let datetime = self.parse_datetime()?;
+        let loc = report.track("location", self.parse_location()).ok();
+        let datetime = report.track("datetime", self.parse_datetime())?;
         // println!("datetime: {:?}", datetime);
         // println!("location: {:?}", loc);
         // // let apartment = ApartmentBuilder::default().id(self.id).
         let mut floor_elevator = false;
         let mut floor_number = None;
+        let mut floor_total = None;
         if let Some(floor) = floor {
-            floor_elevator = floor.1;
             floor_number = Some(floor.0);
+            floor_total = floor.1;
+            floor_elevator = floor.2;
         }
-        let distance = if let Some(l) = loc.as_ref() {
-            Some(calculate_distance(
-                l.latitude,
-                l.longitude,
-                TARGET_LOCATION.latitude,
-                TARGET_LOCATION.longitude,
-            ) as i64)
-        } else {
-            None
-        };
-        Ok(ApartmentBuilder::default()
+        let distance = loc.as_ref().map(target_distance);
+        ApartmentBuilder::default()
             .url(self.url)
             .id(self.id)
             .datetime(datetime)
@@ -250,10 +215,18 @@ impl ApartmentPage {
             .price(price)
             .area(area)
             .floor(floor_number)
+            .total_floors(floor_total)
+            .is_ground_floor(floor_number == Some(1))
+            .is_top_floor(matches!((floor_number, floor_total), (Some(f), Some(t)) if f >= t))
             .location(loc)
             .distance(distance)
+            .is_agency(seller.as_ref().is_some_and(|s| s.is_agency))
+            .commission(descr.as_ref().and_then(|d| d.commission))
+            .seller(seller)
             .description(descr)
-            .build()?)
+            .description_text(descr_text)
+            .build()
+            .map_err(|e| SSError::field("apartment", e))
     }
 }
 // struct SearchPage {
@@ -275,89 +248,62 @@ impl<'a> SearchPageBuilder<'a> {
         self.url = url;
         self
     }
-    pub fn min_price(mut self, price: u32) -> Self {
-        *self.args.entry(PA_PRICE_LOW).or_insert(price) = price;
+    /// 0 means no limit, the form field is left out
+    fn limit(mut self, name: &'a str, value: u32) -> Self {
+        match value {
+            0 => self.args.remove(name),
+            value => self.args.insert(name, value),
+        };
         self
     }
 
-    pub fn max_price(mut self, price: u32) -> Self {
-        *self.args.entry(PA_PRICE_HIGH).or_insert(price) = price;
-        self
+    pub fn min_price(self, price: u32) -> Self {
+        self.limit(PA_PRICE_LOW, price)
     }
 
-    pub fn min_area(mut self, area: u32) -> Self {
-        *self.args.entry(PA_AREA_LOW).or_insert(area) = area;
-        self
+    pub fn max_price(self, price: u32) -> Self {
+        self.limit(PA_PRICE_HIGH, price)
+    }
+
+    pub fn min_area(self, area: u32) -> Self {
+        self.limit(PA_AREA_LOW, area)
     }
 
-    pub fn build(self) -> Result<SearchPageRequest, Box<dyn std::error::Error>> {
+    pub fn build(self) -> Result<SearchPageRequest, SSError> {
+        let mut filters: Vec<(String, String)> = self
+            .args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        filters.sort();
         Ok(SearchPageRequest {
             url: reqwest::Url::parse(self.url)?,
             body: serde_urlencoded::to_string(&self.args)?,
+            filters,
         })
     }
 }
 
+/// Filter form of the search, see `session::SearchSession`
+#[derive(Debug, Clone, PartialEq)]
 pub struct SearchPageRequest {
     pub url: reqwest::Url, //&'a str,
     pub body: String,
-}
-impl SearchPageRequest {
-    pub async fn request(
-        self,
-        client: &reqwest::Client,
-    ) -> Result<SearchPage, Box<dyn std::error::Error>> {
-        let request_post = client
-            .post(self.url.clone())
-            .header(reqwest::header::CONTENT_LENGTH, self.body.len())
-            .header(reqwest::header::CONNECTION, "keep-alive")
-            .header(reqwest::header::ACCEPT, "*/*")
-            // .header(reqwest::header::ACCEPT_ENCODING, "gzip, deflate, br")
-            .header(
-                reqwest::header::CONTENT_TYPE,
-                "application/x-www-form-urlencoded",
-            )
-            .header(reqwest::header::USER_AGENT, "agent")
-            .body(self.body.clone())
-            .build()?;
-
-        let response = client.execute(request_post.try_clone().unwrap()).await?;
-        log::debug!(
-            "1st POST: status:{} headers:{:?}",
-            response.status(),
-            response.headers()
-        );
-        let _text = response.text().await?;
-        // println!("page size: {} KB", text.len() as f64 / 1000.0);
-        if false {
-            let _request_get = client
-                .get(self.url.clone())
-                .header(reqwest::header::CONNECTION, "keep-alive")
-                .header(reqwest::header::ACCEPT, "*/*")
-                .header(reqwest::header::USER_AGENT, "agent")
-                .build()?;
-        }
-        let response = client.execute(request_post.try_clone().unwrap()).await?;
-        log::debug!(
-            "2nd POST: status:{} headers:{:?}",
-            response.status(),
-            response.headers()
-        );
-        let text = response.text().await?;
-        log::info!("Page size: {} KB", text.len() as f64 / 1000.0);
-        // println!("{}", text);
-        Ok(SearchPage::new(
-            self.url,
-            scraper::Html::parse_document(text.as_str()),
-        ))
-        // Err(Box::new(SSError::Http(self.url.to_string())))
-    }
+    /// Form fields and the values the results page has to show
+    pub filters: Vec<(String, String)>,
 }
 
 #[derive(Debug)]
 pub struct ApartmentPageRequest {
     pub id: String,
     pub href: String,
+    /// Validators of the previous fetch, if the page is known
+    pub validators: Validators,
+    /// The page was fetched before
+    pub known: bool,
+    pub summary: ListingSummary,
+    /// Search profiles which have found the listing
+    pub profiles: Vec<String>,
 }
 impl ApartmentPageRequest {
     // fn new(id: String, url: String) -> Self {
@@ -366,16 +312,21 @@ impl ApartmentPageRequest {
     //         url: reqwest::Url::parse(url).unwrap(),
     //     }
     // }
-    pub async fn request(
-        &self,
-        client: Arc<reqwest::Client>,
-    ) -> Result<ApartmentPage, Box<dyn std::error::Error + Send + Sync>> {
+    /// None when the page is not modified since the previous fetch
+    pub async fn request(&self, fetcher: Arc<Fetcher>) -> Result<Option<ApartmentPage>, SSError> {
         let url = reqwest::Url::parse(self.href.as_str())?;
-        let response = client.get(url.clone()).send().await?;
-        if 200 != response.status() {
-            return Err(Box::new(SSError::Http(url.to_string())));
-        }
-        let body = response.text().await?;
+        let timer = Metrics::get().detail_fetch.start_timer();
+        let fetched = fetcher
+            .conditional(|client| client.get(url.clone()), &self.validators)
+            .await;
+        timer.observe_duration();
+        let (body, validators) = match fetched? {
+            Fetched::Modified { body, validators } => (body, validators),
+            Fetched::NotModified => {
+                log::info!("Page is not modified: id({}), url({})", self.id, self.href);
+                return Ok(None);
+            }
+        };
         // response.status().eq(reqwest::Response::St)
         log::info!(
             "Got page: id({}) size({} KB), ulr({})",
@@ -384,12 +335,13 @@ impl ApartmentPageRequest {
             self.href
         );
         // println!("len: {:?}", body);
-        // Err(Box::new(SSError::Empty))
-        Ok(ApartmentPage::new(
+        let mut page = ApartmentPage::new(
             self.href.clone(),
             self.id.clone(),
             Html::parse_document(&body),
-        ))
+        );
+        page.validators = validators;
+        Ok(Some(page))
     }
 }
 
@@ -408,67 +360,135 @@ impl SearchPage {
         }
     }
 
-    pub fn next(&mut self) -> Result<ApartmentPageRequest, Box<dyn std::error::Error>> {
-        Ok(self.apartments.pop().ok_or(Box::new(SSError::Empty))?)
+    pub fn next_request(&mut self) -> Result<ApartmentPageRequest, SSError> {
+        self.apartments.pop().ok_or(SSError::Empty)
     }
 
-    pub fn parse(mut self) -> Result<Self, Box<dyn std::error::Error>> {
-        let selector_path = "#filter_frm > table:nth-child(3) > tbody:nth-child(1)";
+    /// Summary out of the search results row, the fields which are not
+    /// found are left empty
+    fn parse_summary(&self, row: ElementRef, id: &str, href: &str) -> ListingSummary {
+        let spec = SelectorSpec::global();
+        let field = |name| spec.extract_row(row, name);
+        let (district, street) = match field("location") {
+            Ok(location) => {
+                let location = location.into_string();
+                let line_break = LINE_BREAK.get_or_init(|| {
+                    Regex::new(r"(?i)<br\s*/?>").expect("line break regex is valid")
+                });
+                let mut lines = line_break
+                    .split(&location)
+                    .map(|l| {
+                        Html::parse_fragment(l)
+                            .root_element()
+                            .text()
+                            .collect::<String>()
+                            .trim()
+                            .to_string()
+                    })
+                    .filter(|l| !l.is_empty())
+                    .collect::<Vec<_>>();
+                match lines.len() {
+                    0 => (None, None),
+                    1 => (None, lines.pop()),
+                    _ => {
+                        let street = lines.pop();
+                        (Some(lines.join(", ")), street)
+                    }
+                }
+            }
+            Err(_) => (None, None),
+        };
+        ListingSummary {
+            id: id.to_string(),
+            url: href.to_string(),
+            thumbnail: field("thumbnail")
+                .ok()
+                .and_then(|t| self.url.join(&t.into_string()).ok())
+                .map(|t| t.to_string()),
+            text: field("text")
+                .map(|t| t.into_string().trim().to_string())
+                .unwrap_or_default(),
+            district,
+            street,
+            rooms: field("rooms").and_then(|v| v.as_f64("rooms")).ok(),
+            area: field("area").and_then(|v| v.as_f64("area")).ok(),
+            floor: field("floor").and_then(|v| v.as_i64("floor")).ok(),
+            total_floors: field("total_floors")
+                .and_then(|v| v.as_i64("total_floors"))
+                .ok(),
+            series: field("series")
+                .map(|v| v.into_string().trim().to_string())
+                .ok()
+                .filter(|s| !s.is_empty()),
+            price: field("price")
+                .ok()
+                .and_then(|v| ListingSummary::parse_price(&v.into_string())),
+        }
+    }
+
+    pub fn parse(mut self) -> Result<Self, SSError> {
+        let spec = &SelectorSpec::global().search;
+        let selector_path = spec.results.as_str();
         // println!("Use selector: '{}'", selector_path);
-        let selector = Selector::parse(&selector_path).unwrap();
+        let selector = spec.results_selector()?;
         let attr_name = "href";
         let app = self
             .page
-            .select(&selector)
+            .select(selector)
             .next()
-            .ok_or(Box::new(SSError::Selector(selector_path.to_string())))?;
-        let search_results = app.children().filter_map(|a| match a.value().as_element() {
-            Some(el) if el.attr("style") == None => match el.attr("id") {
-                Some(id) if id != "head_line" => Some(a),
-                _ => None,
-            },
-            _ => None,
+            .ok_or(SSError::Selector(selector_path.to_string()))?;
+        let search_results = app.children().filter(|a| match a.value().as_element() {
+            Some(el) if el.attr("style").is_none() => {
+                matches!(el.attr("id"), Some(id) if id != spec.header_row_id)
+            }
+            _ => false,
         });
 
         self.apartments = search_results
             .clone()
-            .map(|l| {
-                let id = l.value().as_element().unwrap().attr("id").unwrap();
-                (id, l)
-            })
-            .filter_map(|t| match t.1.children().next() {
-                Some(n) => Some((t.0, n)),
-                _ => None,
-            })
-            .filter_map(|n| match n.1.next_sibling() {
-                Some(s) => Some((n.0, s)),
-                _ => None,
-            })
-            .filter_map(|s| match s.1.first_child() {
-                Some(c) if c.value().is_element() => Some((s.0, c.value().as_element().unwrap())),
-                _ => None,
-            })
-            .filter_map(|c| match c.1.attr(attr_name) {
-                Some(href) => {
-                    // println!("hreg: {:?}", href);
-                    Some(ApartmentPageRequest {
-                        id: c.0.to_string(),
-                        href: format!(
-                            "{}://{}{}",
-                            self.url.scheme(),
-                            self.url.host_str().unwrap(),
-                            href.to_string()
-                        ),
-                    })
-                }
-                _ => None,
+            .filter_map(|l| l.value().as_element()?.attr("id").map(|id| (id, l)))
+            .filter_map(|t| t.1.children().next().map(|n| (t.0, t.1, n)))
+            .filter_map(|n| n.2.next_sibling().map(|s| (n.0, n.1, s)))
+            .filter_map(|s| {
+                s.2.first_child()
+                    .and_then(|c| c.value().as_element())
+                    .map(|c| (s.0, s.1, c))
             })
+            .filter_map(
+                |c| match c.2.attr(attr_name).map(|href| self.url.join(href)) {
+                    Some(Ok(href)) => {
+                        // println!("hreg: {:?}", href);
+                        let summary = ElementRef::wrap(c.1)
+                            .map(|row| self.parse_summary(row, c.0, href.as_str()))
+                            .unwrap_or_default();
+                        Some(ApartmentPageRequest {
+                            id: c.0.to_string(),
+                            href: href.to_string(),
+                            validators: Validators::default(),
+                            known: false,
+                            summary,
+                            profiles: vec![],
+                        })
+                    }
+                    _ => None,
+                },
+            )
             .collect();
         log::info!("Found {} appartments", self.apartments.len());
         Ok(self)
     }
 }
 
+/// Meters to the target location
+pub fn target_distance(location: &Location) -> i64 {
+    calculate_distance(
+        location.latitude,
+        location.longitude,
+        TARGET_LOCATION.latitude,
+        TARGET_LOCATION.longitude,
+    ) as i64
+}
+
 // function to calculate the distance between two points
 fn calculate_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
     let delta_lat = (lat2 - lat1).to_radians();
@@ -480,3 +500,113 @@ fn calculate_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
 
     EARTH_RADIUS * c
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH_PAGE: &str = r#"<html><body><form id="filter_frm"><table></table><table></table><table><tbody><tr id="head_line"><td>header</td></tr><tr id="tr_53812"><td><input type="checkbox"></td><td><a href="/msg/ru/real-estate/flats/riga/centre/abcde.html"><img src="https://i.ss.lv/gallery/thumb.jpg"></a></td><td><div class="d1"><a class="am">Сдается светлая квартира</a></div></td><td>Центр<br>Brīvības 100</td><td>3</td><td>80</td><td>5/7</td><td>Сталинка</td><td>1,200  €</td></tr></tbody></table></form></body></html>"#;
+
+    fn ad_page(floor: &str) -> ApartmentPage {
+        let html = format!(
+            r#"<html><body><table><tr><td id="tdo_4">{}</td></tr>
+            <tr><td class="msg_footer">x</td><td class="msg_footer">Дата: 12.05.2023 14:31</td></tr></table>
+            </body></html>"#,
+            floor
+        );
+        ApartmentPage::new(
+            "https://www.ss.lv/msg/abcde.html".into(),
+            "abcde".into(),
+            Html::parse_document(&html),
+        )
+    }
+
+    #[test]
+    fn parses_floor() {
+        assert_eq!(
+            ad_page("5/7/лифт").parse_floor_f_t_e().unwrap(),
+            (5, Some(7), true)
+        );
+        assert_eq!(
+            ad_page("3/5").parse_floor_f_t_e().unwrap(),
+            (3, Some(5), false)
+        );
+        assert_eq!(ad_page("2").parse_floor_f_t_e().unwrap(), (2, None, false));
+        assert!(ad_page("цоколь").parse_floor_f_t_e().is_err());
+    }
+
+    fn contacts_page(contacts: &str) -> ApartmentPage {
+        let html = format!("<html><body><table>{}</table></body></html>", contacts);
+        ApartmentPage::new(String::new(), String::new(), Html::parse_document(&html))
+    }
+
+    #[test]
+    fn parses_seller() {
+        let company = contacts_page(
+            r#"<tr><td class="ads_contacts_name">Компания:</td><td class="ads_contacts"> Re/Max </td></tr>
+            <tr><td class="ads_contacts_name">Телефон:</td><td class="ads_contacts" id="phone_td_1">***</td></tr>"#,
+        )
+        .parse_seller()
+        .unwrap();
+        assert!(company.is_agency && company.has_phone);
+        assert_eq!(company.company.as_deref(), Some("Re/Max"));
+
+        let agent = contacts_page(
+            r#"<tr><td class="ads_contacts_name">Aģents:</td><td class="ads_contacts">Jānis</td></tr>"#,
+        )
+        .parse_seller()
+        .unwrap();
+        assert!(agent.is_agency && !agent.has_phone);
+        assert_eq!(agent.company, None);
+
+        let owner = contacts_page(
+            r#"<tr><td class="ads_contacts_name">Телефон:</td><td class="ads_contacts" id="phone_td_1">***</td></tr>"#,
+        )
+        .parse_seller()
+        .unwrap();
+        assert!(!owner.is_agency && owner.has_phone);
+    }
+
+    #[test]
+    fn marks_first_and_last_floors() {
+        let floors = |floor: &str| {
+            let a = ad_page(floor).parse().unwrap();
+            (a.is_ground_floor, a.is_top_floor)
+        };
+        assert_eq!(floors("5/5"), (false, true));
+        assert_eq!(floors("4/5/лифт"), (false, false));
+        assert_eq!(floors("1/5"), (true, false));
+        // A single storey house is both
+        assert_eq!(floors("1/1"), (true, true));
+        // Unknown total is not the top
+        assert_eq!(floors("9"), (false, false));
+    }
+
+    #[test]
+    fn parses_listing_summary() {
+        let url = reqwest::Url::parse("https://www.ss.lv/ru/real-estate/flats/riga/").unwrap();
+        let mut sp = SearchPage::new(url, Html::parse_document(SEARCH_PAGE))
+            .parse()
+            .unwrap();
+        let apr = sp.next_request().unwrap();
+        assert!(sp.next_request().is_err());
+        assert_eq!(apr.id, "tr_53812");
+        assert_eq!(
+            apr.href,
+            "https://www.ss.lv/msg/ru/real-estate/flats/riga/centre/abcde.html"
+        );
+        let s = apr.summary;
+        assert_eq!(s.text, "Сдается светлая квартира");
+        assert_eq!(s.district.as_deref(), Some("Центр"));
+        assert_eq!(s.street.as_deref(), Some("Brīvības 100"));
+        assert_eq!(s.rooms, Some(3.0));
+        assert_eq!(s.area, Some(80.0));
+        assert_eq!((s.floor, s.total_floors), (Some(5), Some(7)));
+        assert_eq!(s.series.as_deref(), Some("Сталинка"));
+        assert_eq!(s.price, Some(1200));
+        assert_eq!(
+            s.thumbnail.as_deref(),
+            Some("https://i.ss.lv/gallery/thumb.jpg")
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Deserialize;
+
+use crate::{config::Config, error::SSError};
+
+static DEFAULT_SPEC: &str = include_str!("../selectors.toml");
+static SPEC: OnceLock<SelectorSpec> = OnceLock::new();
+
+/// Fields the ad page parser reads, with the target the value has to be of
+/// when the parser takes only one
+const PAGE_FIELDS: &[(&str, Option<Target>)] = &[
+    ("city", None),
+    ("district", None),
+    ("address", None),
+    ("price", None),
+    ("area", None),
+    ("rooms", None),
+    ("parking", Some(Target::Bool)),
+    ("description", None),
+    ("floor", None),
+    ("total_floors", None),
+    ("floor_elevator", Some(Target::Bool)),
+    ("latitude", None),
+    ("longitude", None),
+    ("datetime", Some(Target::Datetime)),
+    ("contacts_script", None),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Content {
+    #[default]
+    Html,
+    Text,
+    /// Text without the nested tables, which hold the ad options
+    TextWithoutTables,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    #[default]
+    String,
+    F64,
+    I64,
+    Bool,
+    Datetime,
+}
+
+/// How to extract a single field out of the page
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldSpec {
+    pub selector: String,
+    pub attribute: Option<String>,
+    #[serde(default)]
+    pub content: Content,
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub target: Target,
+    pub format: Option<String>,
+    #[serde(skip)]
+    compiled: OnceLock<(Selector, Option<Regex>)>,
+}
+
+impl FieldSpec {
+    /// Selector and regex, compiled once
+    fn compiled(&self) -> Result<&(Selector, Option<Regex>), SSError> {
+        if let Some(compiled) = self.compiled.get() {
+            return Ok(compiled);
+        }
+        let selector = parse_selector(&self.selector)?;
+        let regex = self.regex.as_deref().map(Regex::new).transpose()?;
+        Ok(self.compiled.get_or_init(|| (selector, regex)))
+    }
+}
+
+/// Compiled selectors and labels of the seller spec
+#[derive(Debug, Clone)]
+pub struct SellerMatchers {
+    pub label: Selector,
+    pub phone: Selector,
+    pub company_labels: Regex,
+    pub agency_labels: Regex,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SellerSpec {
+    pub label: String,
+    pub phone: String,
+    pub company_labels: String,
+    pub agency_labels: String,
+    #[serde(skip)]
+    compiled: OnceLock<SellerMatchers>,
+}
+
+impl SellerSpec {
+    /// Selectors and labels, compiled once
+    pub fn matchers(&self) -> Result<&SellerMatchers, SSError> {
+        if let Some(matchers) = self.compiled.get() {
+            return Ok(matchers);
+        }
+        let matchers = SellerMatchers {
+            label: parse_selector(&self.label)?,
+            phone: parse_selector(&self.phone)?,
+            company_labels: Regex::new(&self.company_labels)?,
+            agency_labels: Regex::new(&self.agency_labels)?,
+        };
+        Ok(self.compiled.get_or_init(|| matchers))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContactsSpec {
+    pub inline_scripts: String,
+    #[serde(skip)]
+    compiled: OnceLock<Selector>,
+}
+
+impl ContactsSpec {
+    /// Selector of the inline scripts, compiled once
+    pub fn inline_scripts_selector(&self) -> Result<&Selector, SSError> {
+        compiled_selector(&self.compiled, &self.inline_scripts)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchSpec {
+    pub results: String,
+    pub header_row_id: String,
+    /// Fields of the listing summary, the selectors are relative to the row
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldSpec>,
+    #[serde(skip)]
+    compiled: OnceLock<Selector>,
+}
+
+impl SearchSpec {
+    /// Selector of the results table, compiled once
+    pub fn results_selector(&self) -> Result<&Selector, SSError> {
+        compiled_selector(&self.compiled, &self.results)
+    }
+}
+
+fn compiled_selector<'a>(
+    cell: &'a OnceLock<Selector>,
+    selector: &str,
+) -> Result<&'a Selector, SSError> {
+    if let Some(selector) = cell.get() {
+        return Ok(selector);
+    }
+    let parsed = parse_selector(selector)?;
+    Ok(cell.get_or_init(|| parsed))
+}
+
+/// Typed value of an extracted field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    F64(f64),
+    I64(i64),
+    Bool(bool),
+    Datetime(chrono::NaiveDateTime),
+}
+
+impl Value {
+    pub fn into_string(self) -> String {
+        match self {
+            Value::String(s) => s,
+            Value::F64(v) => v.to_string(),
+            Value::I64(v) => v.to_string(),
+            Value::Bool(v) => v.to_string(),
+            Value::Datetime(v) => v.to_string(),
+        }
+    }
+
+    pub fn as_f64(&self, field: &str) -> Result<f64, SSError> {
+        match self {
+            Value::F64(v) => Ok(*v),
+            Value::I64(v) => Ok(*v as f64),
+            Value::String(s) => s.trim().parse().map_err(|_| SSError::field(field, s)),
+            v => Err(SSError::field(field, format!("{:?}", v))),
+        }
+    }
+
+    pub fn as_i64(&self, field: &str) -> Result<i64, SSError> {
+        match self {
+            Value::I64(v) => Ok(*v),
+            Value::F64(v) => Ok(*v as i64),
+            Value::String(s) => s.trim().parse().map_err(|_| SSError::field(field, s)),
+            v => Err(SSError::field(field, format!("{:?}", v))),
+        }
+    }
+
+    pub fn as_bool(&self, field: &str) -> Result<bool, SSError> {
+        match self {
+            Value::Bool(v) => Ok(*v),
+            v => Err(SSError::field(field, format!("{:?}", v))),
+        }
+    }
+
+    pub fn as_datetime(&self, field: &str) -> Result<chrono::NaiveDateTime, SSError> {
+        match self {
+            Value::Datetime(v) => Ok(*v),
+            v => Err(SSError::field(field, format!("{:?}", v))),
+        }
+    }
+}
+
+/// Selectors and extraction rules of the ss.lv pages, see selectors.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct SelectorSpec {
+    pub fields: BTreeMap<String, FieldSpec>,
+    pub seller: SellerSpec,
+    pub contacts: ContactsSpec,
+    pub search: SearchSpec,
+}
+
+impl Default for SelectorSpec {
+    fn default() -> Self {
+        // The embedded spec is validated by the tests
+        Self::from_toml(DEFAULT_SPEC).unwrap_or_else(|e| panic!("Invalid embedded spec: {}", e))
+    }
+}
+
+impl SelectorSpec {
+    pub fn from_toml(text: &str) -> Result<Self, SSError> {
+        let spec: Self = toml::from_str(text).map_err(|e| SSError::Spec(e.to_string()))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Reads the spec file, the embedded one is used when there is no file
+    pub fn load(path: &str) -> Result<Self, SSError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                log::info!("Using selectors from '{}'", path);
+                Self::from_toml(&text)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("No '{}', using embedded selectors", path);
+                Self::from_toml(DEFAULT_SPEC)
+            }
+            Err(e) => Err(SSError::Spec(format!("{}: {}", path, e))),
+        }
+    }
+
+    /// Loads the spec from the configured location, must be called before
+    /// the first use of `global`, otherwise the embedded spec is used
+    pub fn init() -> Result<&'static Self, SSError> {
+        let spec = Self::load(&Config::selectors_location())?;
+        Ok(SPEC.get_or_init(|| spec))
+    }
+
+    pub fn global() -> &'static Self {
+        SPEC.get_or_init(Self::default)
+    }
+
+    /// Checks that the fields of the parser are there and that every
+    /// selector and regex compiles; the compiled ones are kept
+    fn validate(&self) -> Result<(), SSError> {
+        for (name, target) in PAGE_FIELDS.iter() {
+            let field = self.field(name)?;
+            if let Some(target) = target.filter(|t| *t != field.target) {
+                let target = format!("{:?}", target).to_lowercase();
+                return Err(SSError::Spec(format!(
+                    "target of field '{}' has to be \"{}\"",
+                    name, target
+                )));
+            }
+        }
+        for (name, field) in self.fields.iter().chain(self.search.fields.iter()) {
+            field.compiled()?;
+            if field.target == Target::Datetime && field.format.is_none() {
+                return Err(SSError::Spec(format!(
+                    "no format of datetime field '{}'",
+                    name
+                )));
+            }
+        }
+        self.seller.matchers()?;
+        self.contacts.inline_scripts_selector()?;
+        self.search.results_selector()?;
+        Ok(())
+    }
+
+    pub fn field(&self, name: &str) -> Result<&FieldSpec, SSError> {
+        self.fields
+            .get(name)
+            .ok_or(SSError::Spec(format!("no field '{}'", name)))
+    }
+
+    /// Extracts the field out of the page according to its spec
+    pub fn extract(&self, page: &Html, name: &str) -> Result<Value, SSError> {
+        extract_from(page.root_element(), self.field(name)?, name)
+    }
+
+    /// Extracts the listing summary field out of the search results row
+    pub fn extract_row(&self, row: ElementRef, name: &str) -> Result<Value, SSError> {
+        let spec = self
+            .search
+            .fields
+            .get(name)
+            .ok_or(SSError::Spec(format!("no search field '{}'", name)))?;
+        extract_from(row, spec, name)
+    }
+}
+
+fn extract_from(root: ElementRef, spec: &FieldSpec, name: &str) -> Result<Value, SSError> {
+    let (selector, regex) = spec.compiled()?;
+    let element = root
+        .select(selector)
+        .next()
+        .ok_or(SSError::Selector(spec.selector.clone()))?;
+    let raw = match spec.attribute.as_ref() {
+        Some(attr) => element
+            .value()
+            .attr(attr)
+            .ok_or(SSError::Selector(format!("{}[{}]", spec.selector, attr)))?
+            .to_string(),
+        None => match spec.content {
+            Content::Html => element.inner_html(),
+            Content::Text => element.text().collect(),
+            Content::TextWithoutTables => text_without_tables(element),
+        },
+    };
+    let raw = raw.trim();
+
+    let value = match regex {
+        Some(re) => {
+            let captures = re.captures(raw);
+            if spec.target == Target::Bool {
+                return Ok(Value::Bool(captures.is_some()));
+            }
+            let captures = captures.ok_or(SSError::field(name, raw))?;
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map_or("", |m| m.as_str())
+        }
+        None => raw,
+    };
+
+    match spec.target {
+        Target::String => Ok(Value::String(value.to_string())),
+        Target::F64 => value
+            .parse()
+            .map(Value::F64)
+            .map_err(|_| SSError::field(name, raw)),
+        Target::I64 => value
+            .parse()
+            .map(Value::I64)
+            .map_err(|_| SSError::field(name, raw)),
+        Target::Bool => Ok(Value::Bool(true)),
+        Target::Datetime => {
+            chrono::NaiveDateTime::parse_from_str(value, spec.format.as_deref().unwrap_or_default())
+                .map(Value::Datetime)
+                .map_err(|_| SSError::field(name, raw))
+        }
+    }
+}
+
+pub fn parse_selector(selector_str: &str) -> Result<Selector, SSError> {
+    Selector::parse(selector_str).map_err(|_| SSError::Selector(selector_str.to_string()))
+}
+
+fn text_without_tables(element: ElementRef) -> String {
+    let mut text = String::new();
+    let mut stack: Vec<_> = element.children().collect();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        match node.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(el) if el.name() == "table" => {}
+            Node::Element(el) => {
+                if el.name() == "br" {
+                    text.push('\n');
+                }
+                stack.extend(node.children().rev());
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <table><tr><td id="tdo_3">80 м²</td></tr>
+        <tr><td id="tdo_4">5/7/лифт</td></tr>
+        <tr><td class="msg_footer">x</td><td class="msg_footer">Дата: 12.05.2023 14:31</td></tr></table>
+        <a id="mnu_map" onclick="mnu('map', '&c=56.9585757, 24.1257553, 17');">map</a>
+        </body></html>"#;
+
+    #[test]
+    fn embedded_spec_is_valid() {
+        SelectorSpec::from_toml(DEFAULT_SPEC).unwrap();
+    }
+
+    #[test]
+    fn requires_parser_fields() {
+        let without_datetime = DEFAULT_SPEC.replace("[fields.datetime]", "[fields.datetime_old]");
+        assert!(matches!(
+            SelectorSpec::from_toml(&without_datetime),
+            Err(SSError::Spec(e)) if e.contains("'datetime'")
+        ));
+        let string_parking = DEFAULT_SPEC.replace(
+            "regex = '(?i)парков'\ntarget = \"bool\"",
+            "regex = '(?i)парков'",
+        );
+        assert!(matches!(
+            SelectorSpec::from_toml(&string_parking),
+            Err(SSError::Spec(e)) if e.contains("'parking'")
+        ));
+    }
+
+    #[test]
+    fn extracts_typed_values() {
+        let spec = SelectorSpec::default();
+        let page = Html::parse_document(PAGE);
+        assert_eq!(spec.extract(&page, "area").unwrap(), Value::F64(80.0));
+        assert_eq!(spec.extract(&page, "floor").unwrap(), Value::I64(5));
+        assert_eq!(spec.extract(&page, "total_floors").unwrap(), Value::I64(7));
+        assert_eq!(
+            spec.extract(&page, "floor_elevator").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            spec.extract(&page, "latitude").unwrap(),
+            Value::F64(56.9585757)
+        );
+        assert_eq!(
+            spec.extract(&page, "longitude").unwrap(),
+            Value::F64(24.1257553)
+        );
+        assert!(matches!(
+            spec.extract(&page, "datetime").unwrap(),
+            Value::Datetime(_)
+        ));
+        assert!(matches!(
+            spec.extract(&page, "city"),
+            Err(SSError::Selector(_))
+        ));
+    }
+}
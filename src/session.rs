@@ -0,0 +1,159 @@
+use scraper::Html;
+
+use crate::{
+    error::SSError,
+    fetch::Fetcher,
+    metrics::Metrics,
+    page_handler::{SearchPage, SearchPageRequest},
+    selectors::parse_selector,
+};
+
+// ss.lv keeps the search filters in the session: the filter form is POSTed
+// once, the session cookie remembers it and the plain GET of the same url
+// returns the filtered results afterwards. The results page shows the filter
+// form filled with the applied values, which is used to verify the session.
+
+/// Cookie based filter session of the search, established once and reused
+/// across the cycles
+pub struct SearchSession {
+    request: SearchPageRequest,
+    established: bool,
+}
+
+impl SearchSession {
+    pub fn new(request: SearchPageRequest) -> Self {
+        Self {
+            request,
+            established: false,
+        }
+    }
+
+    pub fn request(&self) -> &SearchPageRequest {
+        &self.request
+    }
+
+    /// Established and its cookies are not expired yet
+    pub fn is_alive(&self, fetcher: &Fetcher) -> bool {
+        self.established && fetcher.has_cookies(&self.request.url)
+    }
+
+    /// Filtered search results, the session is (re)established when needed
+    pub async fn search(&mut self, fetcher: &Fetcher) -> Result<SearchPage, SSError> {
+        if self.is_alive(fetcher) {
+            let text = self.get(fetcher).await?;
+            match self.page(&text) {
+                Ok(page) => return Ok(page),
+                Err(e) => log::warn!("Session is lost ({}), establishing again", e),
+            }
+        }
+        self.established = false;
+        let page = self.establish(fetcher).await?;
+        self.established = true;
+        Ok(page)
+    }
+
+    /// Posts the filter form, the answer may be not filtered yet if there
+    /// was no session cookie before, then the GET follows
+    async fn establish(&self, fetcher: &Fetcher) -> Result<SearchPage, SSError> {
+        log::info!("Establishing the search session");
+        let post = |client: &reqwest::Client| {
+            client
+                .post(self.request.url.clone())
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(self.request.body.clone())
+        };
+        let text = fetcher.text(post).await?;
+        if let Ok(page) = self.page(&text) {
+            return Ok(page);
+        }
+        let text = self.get(fetcher).await?;
+        self.page(&text)
+    }
+
+    async fn get(&self, fetcher: &Fetcher) -> Result<String, SSError> {
+        let text = fetcher
+            .text(|client| client.get(self.request.url.clone()))
+            .await?;
+        log::info!("Page size: {} KB", text.len() as f64 / 1000.0);
+        Ok(text)
+    }
+
+    /// Parsed results, if the filters are applied
+    fn page(&self, text: &str) -> Result<SearchPage, SSError> {
+        Metrics::get().search_page_bytes.observe(text.len() as f64);
+        let html = Html::parse_document(text);
+        verify_filters(&html, &self.request.filters)?;
+        SearchPage::new(self.request.url.clone(), html).parse()
+    }
+}
+
+/// Checks that the filter form of the page holds the expected values
+pub fn verify_filters(html: &Html, filters: &[(String, String)]) -> Result<(), SSError> {
+    let mismatches: Vec<String> = filters
+        .iter()
+        .filter_map(|(name, expected)| {
+            let actual = form_value(html, name);
+            (actual.as_deref() != Some(expected.as_str()))
+                .then(|| format!("{}={:?} instead of {}", name, actual, expected))
+        })
+        .collect();
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(SSError::Session(format!(
+            "filters are not applied: {}",
+            mismatches.join(", ")
+        )))
+    }
+}
+
+/// Value of the input or the selected option of the select
+fn form_value(html: &Html, name: &str) -> Option<String> {
+    let selector = parse_selector(&format!(r#"[name="{}"]"#, name)).ok()?;
+    let element = html.select(&selector).next()?;
+    if element.value().name() == "select" {
+        let option = parse_selector("option[selected]").ok()?;
+        return element
+            .select(&option)
+            .next()
+            .and_then(|o| o.value().attr("value"))
+            .map(|v| v.trim().to_string());
+    }
+    element.value().attr("value").map(|v| v.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters() -> Vec<(String, String)> {
+        vec![
+            ("topt[3][min]".into(), "70".into()),
+            ("topt[8][max]".into(), "1200".into()),
+        ]
+    }
+
+    #[test]
+    fn accepts_applied_filters() {
+        let html = Html::parse_document(
+            r#"<form id="filter_frm"><input name="topt[3][min]" value="70">
+            <select name="topt[8][max]"><option value="">-</option><option value="1200" selected>1200</option></select></form>"#,
+        );
+        verify_filters(&html, &filters()).unwrap();
+    }
+
+    #[test]
+    fn rejects_missing_filters() {
+        let html = Html::parse_document(
+            r#"<form id="filter_frm"><input name="topt[3][min]" value="">
+            <select name="topt[8][max]"><option value="">-</option></select></form>"#,
+        );
+        assert!(matches!(
+            verify_filters(&html, &filters()),
+            Err(SSError::Session(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use sd_notify::NotifyState;
+use serde::Serialize;
+
+use crate::config::Config;
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+static WATCHDOG: OnceLock<Watchdog> = OnceLock::new();
+
+// The main loop reports its progress here. It is stalled when the next
+// report does not come in time, e.g. a request hangs forever. systemd is fed
+// by a separate task only while the loop is not stalled, so a stalled
+// crawler gets killed and restarted by the watchdog.
+
+/// Last outcome of a component of the crawler
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub ok: bool,
+    pub error: Option<String>,
+    /// Local time, "%Y-%m-%d %H:%M:%S"
+    pub checked: String,
+}
+
+/// Answer of /healthz
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// "ok", "degraded" if a component has failed or "stalled"
+    pub status: &'static str,
+    pub started: String,
+    pub cycle_started: Option<String>,
+    pub last_successful_cycle: Option<String>,
+    pub components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+struct State {
+    started: String,
+    /// Next progress report is expected before
+    deadline: Instant,
+    cycle_started: Option<String>,
+    last_success: Option<String>,
+    components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+pub struct Watchdog {
+    state: Mutex<State>,
+}
+
+fn now() -> String {
+    chrono::Local::now().format(DATETIME_FORMAT).to_string()
+}
+
+impl Watchdog {
+    pub fn get() -> &'static Self {
+        WATCHDOG.get_or_init(|| Self {
+            state: Mutex::new(State {
+                started: now(),
+                deadline: Instant::now() + Config::stall_timeout(),
+                cycle_started: None,
+                last_success: None,
+                components: BTreeMap::new(),
+            }),
+        })
+    }
+
+    fn update(f: impl FnOnce(&mut State)) {
+        match Self::get().state.lock() {
+            Ok(mut state) => f(&mut state),
+            Err(e) => log::error!("Watchdog state is poisoned: {}", e),
+        }
+    }
+
+    /// The main loop makes progress
+    pub fn beat() {
+        Self::update(|s| s.deadline = Instant::now() + Config::stall_timeout());
+    }
+
+    /// The main loop is going to wait for the given time on purpose
+    pub fn sleeping(duration: Duration) {
+        Self::update(|s| s.deadline = Instant::now() + duration + Config::stall_timeout());
+    }
+
+    pub fn cycle_started() {
+        Self::update(|s| {
+            s.deadline = Instant::now() + Config::stall_timeout();
+            s.cycle_started = Some(now());
+        });
+    }
+
+    pub fn cycle_finished() {
+        Self::update(|s| {
+            s.deadline = Instant::now() + Config::stall_timeout();
+            s.last_success = Some(now());
+        });
+    }
+
+    pub fn component<T, E: Display>(name: &'static str, result: &Result<T, E>) {
+        let status = ComponentStatus {
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            checked: now(),
+        };
+        Self::update(|s| {
+            s.components.insert(name, status);
+        });
+    }
+
+    pub fn is_stalled() -> bool {
+        Self::get()
+            .state
+            .lock()
+            .map_or(true, |s| Instant::now() > s.deadline)
+    }
+
+    pub fn report() -> HealthReport {
+        let stalled = Self::is_stalled();
+        let state = match Self::get().state.lock() {
+            Ok(state) => state,
+            Err(e) => e.into_inner(),
+        };
+        let status = if stalled {
+            "stalled"
+        } else if state.components.values().any(|c| !c.ok) {
+            "degraded"
+        } else {
+            "ok"
+        };
+        HealthReport {
+            status,
+            started: state.started.clone(),
+            cycle_started: state.cycle_started.clone(),
+            last_successful_cycle: state.last_success.clone(),
+            components: state.components.clone(),
+        }
+    }
+
+    /// Tells systemd the service is up, no-op outside of systemd
+    pub fn ready() {
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+            log::error!("Fail to notify systemd: {}", e);
+        }
+    }
+
+    /// Feeds the systemd watchdog while the main loop is not stalled, returns
+    /// at once if the watchdog is not enabled for the service
+    pub async fn feed() {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return;
+        }
+        let period = Duration::from_micros(usec / 2).max(Duration::from_secs(1));
+        log::info!("Feeding the systemd watchdog every {}s", period.as_secs());
+        let mut interval = tokio::time::interval(period);
+        let mut stalled = false;
+        loop {
+            interval.tick().await;
+            if Self::is_stalled() {
+                if !stalled {
+                    log::error!("Main loop is stalled, the watchdog is not fed anymore");
+                    let _ = sd_notify::notify(false, &[NotifyState::Status("stalled")]);
+                }
+                stalled = true;
+                continue;
+            }
+            stalled = false;
+            if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+                log::error!("Fail to feed the systemd watchdog: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_failed_components() {
+        Watchdog::beat();
+        assert!(!Watchdog::is_stalled());
+        Watchdog::component("search", &Ok::<(), String>(()));
+        assert_eq!(Watchdog::report().status, "ok");
+        Watchdog::component("telegram", &Err::<(), _>("timeout"));
+        let report = Watchdog::report();
+        assert_eq!(report.status, "degraded");
+        assert_eq!(
+            report.components["telegram"].error.as_deref(),
+            Some("timeout")
+        );
+    }
+}
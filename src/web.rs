@@ -0,0 +1,142 @@
+pub mod api;
+pub mod dashboard;
+pub mod feed;
+
+use std::net::SocketAddr;
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+
+use crate::{config::Config, error::SSError, metrics::Metrics, watchdog::Watchdog};
+
+/// Embedded http server, runs until it fails
+pub async fn serve() -> Result<(), SSError> {
+    let addr: SocketAddr = Config::web_bind()
+        .parse()
+        .map_err(|e| SSError::Config(format!("web bind '{}': {}", Config::web_bind(), e)))?;
+    let app = Router::new()
+        .route("/", get(dashboard::index))
+        .route("/map", get(dashboard::map))
+        .route("/health", get(dashboard::health))
+        .route(
+            "/listing/:id/status",
+            post(dashboard::set_status).layer(middleware::from_fn(dashboard::guard)),
+        )
+        .route("/feed/:profile", get(feed::handler))
+        .route("/metrics", get(metrics))
+        .route("/healthz", get(healthz));
+    let app = if Config::api_token().is_empty() {
+        app
+    } else {
+        app.nest("/api/v1", api::router())
+    };
+    log::info!("Web server is listening on {}", addr);
+    axum::Server::try_bind(&addr)
+        .map_err(|e| SSError::Web(e.to_string()))?
+        .serve(app.into_make_service())
+        .await
+        .map_err(|e| SSError::Web(e.to_string()))
+}
+
+/// GET /metrics, Prometheus text format
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        Metrics::render(),
+    )
+}
+
+/// GET /healthz, 503 when the crawler is stalled
+async fn healthz() -> impl IntoResponse {
+    let report = Watchdog::report();
+    let status = match report.status {
+        "stalled" => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (status, Json(report))
+}
+
+/// Runs the blocking database access off the async workers
+pub async fn blocking<T, F>(f: F) -> Result<T, SSError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, SSError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| SSError::Web(e.to_string()))?
+}
+
+/// The given token is the configured one, which is not empty; the same time
+/// for any wrong token of the same length
+pub(crate) fn same_token(given: &str, token: &str) -> bool {
+    !token.is_empty()
+        && given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// The page which has sent the form, its Origin or the Referer without it,
+/// is on the host of the request or of `public_url`; a request with neither
+/// header is refused
+pub(crate) fn same_origin(headers: &HeaderMap, public_url: &str) -> bool {
+    let authority = |url: &str| {
+        let url = url::Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        Some(match url.port_or_known_default() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        })
+    };
+    let Some(source) = headers
+        .get(header::ORIGIN)
+        .or(headers.get(header::REFERER))
+        .and_then(|v| v.to_str().ok())
+        .and_then(authority)
+    else {
+        return false;
+    };
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|host| authority(&format!("http://{}", host)));
+    host.as_ref() == Some(&source) || authority(public_url).as_ref() == Some(&source)
+}
+
+/// Text for the html and xml content and attributes
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(crate) fn status_code(e: &SSError) -> StatusCode {
+    match e {
+        SSError::NotFound(_) => StatusCode::NOT_FOUND,
+        SSError::Conflict(_) => StatusCode::CONFLICT,
+        SSError::Unauthorized => StatusCode::UNAUTHORIZED,
+        SSError::Forbidden(_) => StatusCode::FORBIDDEN,
+        SSError::Request(_) | SSError::FieldParse { .. } => StatusCode::BAD_REQUEST,
+        SSError::Json(e) if !e.is_io() => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for SSError {
+    fn into_response(self) -> Response {
+        let status = status_code(&self);
+        if status.is_server_error() {
+            log::error!("Web request has failed: {}", self);
+        }
+        (status, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    area::AreaFilter,
+    config::Config,
+    db::{
+        price::PriceRecord,
+        profile::SearchProfile,
+        record::{ApartmentRecrod, RecordQuery},
+        status::StatusRecord,
+    },
+    error::SSError,
+    export::Listing,
+};
+
+use super::{blocking, same_token, status_code};
+
+const PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Routes of /api/v1, every request needs the token
+pub fn router() -> Router {
+    Router::new()
+        .route("/listings", get(listings))
+        .route("/listings/:id", get(listing))
+        .route("/profiles", get(profiles).post(create_profile))
+        .route(
+            "/profiles/:name",
+            get(profile).put(update_profile).delete(delete_profile),
+        )
+        .route_layer(middleware::from_fn(auth))
+}
+
+/// Error of the api as `{"error": "..."}`
+pub struct ApiError(SSError);
+
+impl From<SSError> for ApiError {
+    fn from(value: SSError) -> Self {
+        ApiError(value)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(value: JsonRejection) -> Self {
+        ApiError(SSError::Request(value.body_text()))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = status_code(&self.0);
+        if status.is_server_error() {
+            log::error!("Api request has failed: {}", self.0);
+        }
+        let body = Json(serde_json::json!({ "error": self.0.to_string() }));
+        if status == StatusCode::UNAUTHORIZED {
+            return (status, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response();
+        }
+        (status, body).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// "Authorization: Bearer <token>" matches the configured token
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    given.is_some_and(|given| same_token(given, token))
+}
+
+async fn auth<B>(request: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    if !authorized(request.headers(), &Config::api_token()) {
+        return Err(SSError::Unauthorized.into());
+    }
+    Ok(next.run(request).await)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListingPage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<Listing>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PricePoint {
+    /// UTC time the price was seen first, "%Y-%m-%d %H:%M:%S"
+    pub seen: String,
+    pub price: String,
+    pub price_eur: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListingDetail {
+    #[serde(flatten)]
+    pub listing: Listing,
+    pub brief: String,
+    pub description: Option<String>,
+    /// Profiles which have found the listing
+    pub profiles: Vec<String>,
+    pub price_history: Vec<PricePoint>,
+}
+
+/// GET /api/v1/listings, the filters of the dashboard plus limit and offset
+async fn listings(Query(params): Query<HashMap<String, String>>) -> ApiResult<Json<ListingPage>> {
+    let mut query = RecordQuery::from_params(&params)?;
+    let limit = query.limit.unwrap_or(PAGE_SIZE).min(MAX_PAGE_SIZE);
+    query.limit = Some(limit);
+    let page = blocking(move || {
+        let items = query
+            .select()?
+            .into_iter()
+            .map(|(r, status)| Listing::new(r, status))
+            .collect();
+        Ok(ListingPage {
+            total: query.count()?,
+            offset: query.offset,
+            limit,
+            items,
+        })
+    })
+    .await?;
+    Ok(Json(page))
+}
+
+/// GET /api/v1/listings/:id
+async fn listing(Path(id): Path<String>) -> ApiResult<Json<ListingDetail>> {
+    let detail = blocking(move || {
+        let mut records = ApartmentRecrod::select_by_id(&id)?;
+        if records.is_empty() {
+            return Err(SSError::NotFound(format!("listing '{}'", id)));
+        }
+        let profiles = records.iter().map(|r| r.profile.value.clone()).collect();
+        let price_history = PriceRecord::select_by_id(&id)?
+            .into_iter()
+            .map(|p| PricePoint {
+                seen: p.seen.value,
+                price: p.price.value,
+                price_eur: p.price_eur.value,
+            })
+            .collect();
+        let status = StatusRecord::get(&id)?;
+        let latest = records.remove(0);
+        Ok(ListingDetail {
+            brief: latest.brief.value.clone(),
+            description: latest.description.value.clone(),
+            listing: Listing::new(latest, status),
+            profiles,
+            price_history,
+        })
+    })
+    .await?;
+    Ok(Json(detail))
+}
+
+/// Search profile, the name is taken from the path on update
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub price_low: u32,
+    #[serde(default)]
+    pub price_high: u32,
+    #[serde(default)]
+    pub area_low: u32,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Names of the shipped or configured polygons
+    #[serde(default)]
+    pub areas: Vec<String>,
+    /// GeoJSON Polygon, MultiPolygon, Feature or FeatureCollection
+    #[serde(default)]
+    pub polygons: Option<serde_json::Value>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl From<SearchProfile> for Profile {
+    fn from(p: SearchProfile) -> Self {
+        let areas = p.area_names();
+        Self {
+            name: p.name.value,
+            url: p.url.value,
+            price_low: p.price_low.value,
+            price_high: p.price_high.value,
+            area_low: p.area_low.value,
+            enabled: p.enabled.value,
+            areas,
+            polygons: p
+                .polygons
+                .value
+                .as_deref()
+                .and_then(|text| serde_json::from_str(text).ok()),
+        }
+    }
+}
+
+impl Profile {
+    /// Checked record, the name goes to the urls and the search must build
+    fn record(self) -> Result<SearchProfile, SSError> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(SSError::field("name", self.name));
+        }
+        let mut p = SearchProfile::new();
+        p.name.value = self.name;
+        p.url.value = self.url;
+        p.price_low.value = self.price_low;
+        p.price_high.value = self.price_high;
+        p.area_low.value = self.area_low;
+        p.enabled.value = self.enabled;
+        p.areas.value = Some(self.areas.join(",")).filter(|a| !a.is_empty());
+        p.polygons.value = self.polygons.map(|p| p.to_string());
+        p.request()?;
+        AreaFilter::for_profile(&p)?;
+        Ok(p)
+    }
+}
+
+/// GET /api/v1/profiles
+async fn profiles() -> ApiResult<Json<Vec<Profile>>> {
+    let profiles = blocking(SearchProfile::select_all).await?;
+    Ok(Json(profiles.into_iter().map(Profile::from).collect()))
+}
+
+/// GET /api/v1/profiles/:name
+async fn profile(Path(name): Path<String>) -> ApiResult<Json<Profile>> {
+    let profile = blocking(move || {
+        SearchProfile::select_by_name(&name)?
+            .ok_or(SSError::NotFound(format!("profile '{}'", name)))
+    })
+    .await?;
+    Ok(Json(profile.into()))
+}
+
+/// POST /api/v1/profiles
+async fn create_profile(
+    body: Result<Json<Profile>, JsonRejection>,
+) -> ApiResult<(StatusCode, Json<Profile>)> {
+    let Json(profile) = body?;
+    let record = profile.clone().record()?;
+    blocking(move || {
+        if SearchProfile::select_by_name(&record.name.value)?.is_some() {
+            return Err(SSError::Conflict(format!(
+                "profile '{}' already exists",
+                record.name.value
+            )));
+        }
+        record.insert()
+    })
+    .await?;
+    Ok((StatusCode::CREATED, Json(profile)))
+}
+
+/// PUT /api/v1/profiles/:name
+async fn update_profile(
+    Path(name): Path<String>,
+    body: Result<Json<Profile>, JsonRejection>,
+) -> ApiResult<Json<Profile>> {
+    let Json(mut profile) = body?;
+    profile.name = name;
+    let record = profile.clone().record()?;
+    blocking(move || match record.update()? {
+        true => Ok(()),
+        false => Err(SSError::NotFound(format!(
+            "profile '{}'",
+            record.name.value
+        ))),
+    })
+    .await?;
+    Ok(Json(profile))
+}
+
+/// DELETE /api/v1/profiles/:name, the last profile is kept: without any
+/// the default one would be created again out of the settings
+async fn delete_profile(Path(name): Path<String>) -> ApiResult<StatusCode> {
+    blocking(move || {
+        let profiles = SearchProfile::select_all()?;
+        if !profiles.iter().any(|p| p.name.value == name) {
+            return Err(SSError::NotFound(format!("profile '{}'", name)));
+        }
+        if profiles.len() == 1 {
+            return Err(SSError::Conflict(format!(
+                "profile '{}' is the last one, disable it instead",
+                name
+            )));
+        }
+        match SearchProfile::delete(&name)? {
+            true => Ok(StatusCode::NO_CONTENT),
+            false => Err(SSError::NotFound(format!("profile '{}'", name))),
+        }
+    })
+    .await
+    .map_err(ApiError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apartment::ListingSummary, filter::ApartmentFilter};
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn checks_token() {
+        assert!(authorized(&headers("Bearer secret"), "secret"));
+        assert!(!authorized(&headers("Bearer secreT"), "secret"));
+        assert!(!authorized(&headers("Bearer secret2"), "secret"));
+        assert!(!authorized(&headers("secret"), "secret"));
+        assert!(!authorized(&HeaderMap::new(), "secret"));
+        assert!(!authorized(&headers("Bearer "), ""));
+    }
+
+    #[test]
+    fn validates_profile() {
+        let profile: Profile = serde_json::from_str(
+            r#"{"name": "centre", "url": "https://www.ss.lv/ru/real-estate/flats/riga/centre/hand_over/", "price_high": 800}"#,
+        )
+        .unwrap();
+        assert!(profile.enabled);
+        let record = profile.clone().record().unwrap();
+        assert_eq!(record.price_high.value, 800);
+        let areas = Profile {
+            areas: vec!["centrs".into(), "vecriga".into()],
+            ..profile.clone()
+        };
+        assert_eq!(areas.record().unwrap().area_names().len(), 2);
+        let unknown = Profile {
+            areas: vec!["atlantis".into()],
+            ..profile.clone()
+        };
+        assert!(unknown.record().is_err());
+        let bad = Profile {
+            name: "a/b".into(),
+            ..profile
+        };
+        assert!(matches!(bad.record(), Err(SSError::FieldParse { .. })));
+    }
+
+    #[test]
+    fn profile_without_prices_has_no_limits() {
+        let profile: Profile = serde_json::from_str(
+            r#"{"name": "any", "url": "https://www.ss.lv/ru/real-estate/flats/riga/all/hand_over/"}"#,
+        )
+        .unwrap();
+        let record = profile.record().unwrap();
+        assert!(record.request().unwrap().filters.is_empty());
+        let summary = ListingSummary {
+            price: Some(650),
+            area: Some(55.0),
+            ..Default::default()
+        };
+        let filter = ApartmentFilter::from_config().for_profile(&record);
+        assert_eq!(filter.summary_reject_reason(&summary), None);
+    }
+}
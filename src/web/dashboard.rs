@@ -0,0 +1,507 @@
+use std::{collections::HashMap, fmt::Display};
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use base64::Engine;
+
+use crate::{
+    config::Config,
+    db::{
+        cycle::CycleRecord,
+        record::{ApartmentRecrod, RecordQuery, SortKey},
+        status::{ListingStatus, StatusRecord},
+    },
+    error::SSError,
+};
+
+use super::{blocking, escape, feed::title, same_origin, same_token};
+
+const PAGE_SIZE: usize = 50;
+const MAP_LIMIT: usize = 500;
+const CYCLES: usize = 30;
+/// Riga centre, when there is no marker to fit
+const MAP_CENTRE: (f64, f64) = (56.9496, 24.1052);
+
+/// GET /, the stored apartments with the filter form
+pub async fn index(Query(params): Query<HashMap<String, String>>) -> Result<Html<String>, SSError> {
+    let mut query = RecordQuery::from_params(&params)?;
+    query.limit = Some(query.limit.unwrap_or(PAGE_SIZE));
+    let (rows, total) = {
+        let query = query.clone();
+        blocking(move || Ok((query.select()?, query.count()?))).await?
+    };
+    Ok(Html(render_list(&params, &query, &rows, total)))
+}
+
+/// GET /map, the matching apartments with known coordinates
+pub async fn map(Query(params): Query<HashMap<String, String>>) -> Result<Html<String>, SSError> {
+    let mut query = RecordQuery::from_params(&params)?;
+    query.limit = Some(query.limit.unwrap_or(MAP_LIMIT));
+    let rows = blocking(move || query.select()).await?;
+    Ok(Html(render_map(&params, &rows)))
+}
+
+/// Changes come only from the pages of the dashboard, and with the api
+/// token as the basic auth password when the token is set
+pub async fn guard<B>(request: Request<B>, next: Next<B>) -> Response {
+    if !same_origin(request.headers(), &Config::public_url()) {
+        return SSError::Forbidden("the form is not sent by the dashboard".into()).into_response();
+    }
+    let token = Config::api_token();
+    if !token.is_empty() && !authorized(request.headers(), &token) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"rentbot\"")],
+            SSError::Unauthorized.to_string(),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// "Authorization: Basic" with any user and the token as the password, the
+/// browsers ask for it
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| {
+            base64::engine::general_purpose::STANDARD
+                .decode(v.trim())
+                .ok()
+        })
+        .and_then(|v| String::from_utf8(v).ok())
+        .is_some_and(|credentials| {
+            credentials
+                .split_once(':')
+                .is_some_and(|(_, password)| same_token(password, token))
+        })
+}
+
+/// POST /listing/:id/status, form fields "status" and "back" (the page to
+/// return to)
+pub async fn set_status(
+    Path(id): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Redirect, SSError> {
+    let status = ListingStatus::parse(form.get("status").map_or("", |s| s.as_str()))?;
+    blocking(move || StatusRecord::set(&id, status)).await?;
+    // Only the local pages, not an arbitrary site
+    let back = form
+        .get("back")
+        .filter(|b| b.starts_with('/') && !b.starts_with("//"))
+        .map_or("/", |b| b.as_str());
+    Ok(Redirect::to(back))
+}
+
+/// GET /health, parser health and the last crawl cycles
+pub async fn health() -> Result<Html<String>, SSError> {
+    let cycles = blocking(|| CycleRecord::select_last(CYCLES)).await?;
+    Ok(Html(render_health(&cycles)))
+}
+
+fn label(status: ListingStatus) -> &'static str {
+    match status {
+        ListingStatus::New => "новое",
+        ListingStatus::Interested => "интересно",
+        ListingStatus::Contacted => "связались",
+        ListingStatus::Rejected => "отклонено",
+    }
+}
+
+fn or_dash<T: Display>(value: Option<T>) -> String {
+    value.map_or("—".to_string(), |v| v.to_string())
+}
+
+/// Url of the page with some parameters replaced, empty values are dropped
+fn href(path: &str, params: &HashMap<String, String>, replace: &[(&str, String)]) -> String {
+    let mut params: std::collections::BTreeMap<&str, &str> = params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    for (k, v) in replace {
+        params.insert(k, v);
+    }
+    params.retain(|_, v| !v.is_empty());
+    match serde_urlencoded::to_string(&params) {
+        Ok(query) if !query.is_empty() => format!("{}?{}", path, query),
+        _ => path.to_string(),
+    }
+}
+
+fn layout(title: &str, body: &str, head: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="ru">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{} — rentbot_sslv</title>
+<style>
+body {{ font-family: sans-serif; margin: 1em; }}
+nav a {{ margin-right: 1em; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border-bottom: 1px solid #ddd; padding: 4px 6px; text-align: left; vertical-align: top; }}
+form.filter input {{ width: 6em; }}
+form.status {{ display: inline; }}
+button.current {{ font-weight: bold; }}
+tr.rejected {{ color: #999; }}
+tr.interested {{ background: #eef8ee; }}
+pre {{ background: #f4f4f4; padding: 0.5em; }}
+</style>
+{}
+</head>
+<body>
+<nav><a href="/">Квартиры</a><a href="/map">Карта</a><a href="/health">Состояние</a></nav>
+<h1>{}</h1>
+{}
+</body>
+</html>
+"#,
+        escape(title),
+        head,
+        escape(title),
+        body
+    )
+}
+
+/// Filter form, kept filled with the current values
+fn filter_form(path: &str, params: &HashMap<String, String>) -> String {
+    let value = |name: &str| escape(params.get(name).map_or("", |v| v.as_str()));
+    let input = |name: &str, label: &str| {
+        format!(
+            r#"<label>{} <input name="{}" value="{}"></label> "#,
+            label,
+            name,
+            value(name)
+        )
+    };
+    let select = |name: &str, label: &str, options: &[(&str, &str)]| {
+        let current = params.get(name).map_or("", |v| v.as_str());
+        let options: String = options
+            .iter()
+            .map(|(v, l)| {
+                format!(
+                    r#"<option value="{}"{}>{}</option>"#,
+                    v,
+                    if *v == current { " selected" } else { "" },
+                    l
+                )
+            })
+            .collect();
+        format!(
+            r#"<label>{} <select name="{}">{}</select></label> "#,
+            label, name, options
+        )
+    };
+    let statuses: Vec<(&str, &str)> = std::iter::once(("", "все"))
+        .chain(ListingStatus::ALL.iter().map(|s| (s.as_str(), label(*s))))
+        .collect();
+    format!(
+        r#"<form class="filter" method="get" action="{}">
+{}{}{}{}{}{}{}{}
+<button type="submit">Показать</button> <a href="{}">сбросить</a>
+<input type="hidden" name="sort" value="{}"><input type="hidden" name="order" value="{}">
+</form>"#,
+        path,
+        input("price_min", "цена от"),
+        input("price_max", "до"),
+        input("area_min", "площадь от"),
+        input("area_max", "до"),
+        input("district", "район"),
+        input("distance_max", "дист. до, м"),
+        input("profile", "профиль"),
+        select("status", "статус", &statuses),
+        path,
+        value("sort"),
+        value("order"),
+    )
+}
+
+fn status_forms(id: &str, current: ListingStatus, back: &str) -> String {
+    ListingStatus::ALL
+        .iter()
+        .map(|status| {
+            format!(
+                r#"<form class="status" method="post" action="/listing/{}/status"><input type="hidden" name="status" value="{}"><input type="hidden" name="back" value="{}"><button type="submit"{}>{}</button></form>"#,
+                urlencoding::encode(id),
+                status.as_str(),
+                escape(back),
+                if *status == current { r#" class="current" disabled"# } else { "" },
+                label(*status)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn render_list(
+    params: &HashMap<String, String>,
+    query: &RecordQuery,
+    rows: &[(ApartmentRecrod, ListingStatus)],
+    total: usize,
+) -> String {
+    let back = href("/", params, &[]);
+    let sort_link = |key: SortKey, label: &str| {
+        let (order, mark) = match (query.sort == key, query.descending) {
+            (true, true) => ("asc", " ▼"),
+            (true, false) => ("desc", " ▲"),
+            (false, _) => ("desc", ""),
+        };
+        format!(
+            r#"<a href="{}">{}{}</a>"#,
+            escape(&href(
+                "/",
+                params,
+                &[
+                    ("sort", key.as_str().to_string()),
+                    ("order", order.to_string()),
+                    ("offset", String::new()),
+                ]
+            )),
+            label,
+            mark
+        )
+    };
+    let mut body = filter_form("/", params);
+    body.push_str(&format!("<p>Найдено: {}</p>\n", total));
+    body.push_str(&format!(
+        "<table>\n<tr><th>{}</th><th>{}</th><th>Комн.</th><th>{}</th><th>Район</th><th>Адрес</th><th>{}</th><th>Статус</th></tr>\n",
+        sort_link(SortKey::Datetime, "Дата"),
+        sort_link(SortKey::Price, "Цена"),
+        sort_link(SortKey::Area, "Площадь"),
+        sort_link(SortKey::Distance, "Дист., м"),
+    ));
+    for (r, status) in rows {
+        body.push_str(&format!(
+            r#"<tr class="{}"><td>{}</td><td><a href="{}" title="{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>
+"#,
+            status.as_str(),
+            escape(&r.datetime.value),
+            escape(&r.url.value),
+            escape(&r.brief.value),
+            escape(&r.price.value),
+            or_dash(r.rooms.value),
+            or_dash(r.area.value),
+            escape(&or_dash(r.district.value.as_ref())),
+            escape(&or_dash(r.address.value.as_ref())),
+            or_dash(r.distance.value),
+            status_forms(&r.id.value, *status, &back),
+        ));
+    }
+    body.push_str("</table>\n");
+
+    let limit = query.limit.unwrap_or(PAGE_SIZE).max(1);
+    let mut pages = vec![];
+    if query.offset > 0 {
+        let offset = query.offset.saturating_sub(limit);
+        pages.push(format!(
+            r#"<a href="{}">← назад</a>"#,
+            escape(&href("/", params, &[("offset", offset.to_string())]))
+        ));
+    }
+    if query.offset + rows.len() < total {
+        pages.push(format!(
+            r#"<a href="{}">дальше →</a>"#,
+            escape(&href(
+                "/",
+                params,
+                &[("offset", (query.offset + limit).to_string())]
+            ))
+        ));
+    }
+    body.push_str(&format!("<p>{}</p>\n", pages.join(" ")));
+    layout("Квартиры", &body, "")
+}
+
+#[derive(serde::Serialize)]
+struct Marker {
+    lat: f64,
+    lon: f64,
+    /// Escaped, the popup is html
+    title: String,
+    url: String,
+    status: &'static str,
+}
+
+pub fn render_map(
+    params: &HashMap<String, String>,
+    rows: &[(ApartmentRecrod, ListingStatus)],
+) -> String {
+    let markers: Vec<Marker> = rows
+        .iter()
+        .filter_map(|(r, status)| {
+            Some(Marker {
+                lat: r.latitude.value?,
+                lon: r.longitude.value?,
+                title: escape(&title(r)),
+                url: escape(&r.url.value),
+                status: label(*status),
+            })
+        })
+        .collect();
+    // "</script>" inside of a string would close the script
+    let markers = serde_json::to_string(&markers)
+        .unwrap_or_else(|_| "[]".to_string())
+        .replace("</", "<\\/");
+    let head = r#"<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>"#;
+    let body = format!(
+        r#"{}
+<div id="map" style="height: 75vh;"></div>
+<script>
+const markers = {};
+const map = L.map('map').setView([{}, {}], 12);
+L.tileLayer('https://{{s}}.tile.openstreetmap.org/{{z}}/{{x}}/{{y}}.png', {{
+  maxZoom: 19,
+  attribution: '&copy; OpenStreetMap'
+}}).addTo(map);
+const points = markers.map((m) => {{
+  L.marker([m.lat, m.lon]).addTo(map)
+    .bindPopup('<a href="' + m.url + '">' + m.title + '</a><br>' + m.status);
+  return [m.lat, m.lon];
+}});
+if (points.length > 0) {{ map.fitBounds(points, {{ padding: [20, 20] }}); }}
+</script>
+"#,
+        filter_form("/map", params),
+        markers,
+        MAP_CENTRE.0,
+        MAP_CENTRE.1,
+    );
+    layout("Карта", &body, head)
+}
+
+pub fn render_health(cycles: &[CycleRecord]) -> String {
+    let mut body = String::new();
+    match cycles.first() {
+        Some(last) => {
+            body.push_str(&format!(
+                "<h2>Последний цикл: {}</h2>\n<pre>{}</pre>\n",
+                escape(&last.finished.value),
+                escape(&last.report.value)
+            ));
+            if !last.alerts.value.is_empty() {
+                body.push_str(&format!("<pre>{}</pre>\n", escape(&last.alerts.value)));
+            }
+        }
+        None => body.push_str("<p>Циклов ещё не было</p>\n"),
+    }
+    body.push_str(
+        "<h2>Циклы</h2>\n<table>\n<tr><th>Начало</th><th>Конец</th><th>Профили</th><th>Найдено</th><th>Запрошено</th><th>Разобрано</th><th>Ошибки</th><th>Предупреждения</th></tr>\n",
+    );
+    for c in cycles {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&c.started.value),
+            escape(&c.finished.value),
+            c.profiles.value,
+            c.found.value,
+            c.fetched.value,
+            c.pages.value,
+            c.failed.value,
+            c.alerts.value.lines().count(),
+        ));
+    }
+    body.push_str("</table>\n");
+    layout("Состояние", &body, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn checks_origin() {
+        let public = "https://rent.example.com/";
+        let local = headers(&[
+            (header::HOST, "127.0.0.1:8080"),
+            (header::ORIGIN, "http://127.0.0.1:8080"),
+        ]);
+        assert!(same_origin(&local, public));
+        let proxied = headers(&[
+            (header::HOST, "127.0.0.1:8080"),
+            (header::REFERER, "https://rent.example.com/?sort=price"),
+        ]);
+        assert!(same_origin(&proxied, public));
+        let foreign = headers(&[
+            (header::HOST, "127.0.0.1:8080"),
+            (header::ORIGIN, "https://evil.example.com"),
+        ]);
+        assert!(!same_origin(&foreign, public));
+        assert!(!same_origin(
+            &headers(&[(header::HOST, "127.0.0.1:8080")]),
+            public
+        ));
+    }
+
+    #[test]
+    fn checks_basic_auth() {
+        // "me:secret"
+        let auth = |value: &str| headers(&[(header::AUTHORIZATION, value)]);
+        assert!(authorized(&auth("Basic bWU6c2VjcmV0"), "secret"));
+        assert!(!authorized(&auth("Basic bWU6c2VjcmV0"), "other"));
+        assert!(!authorized(&auth("Bearer secret"), "secret"));
+        assert!(!authorized(&HeaderMap::new(), "secret"));
+    }
+
+    #[test]
+    fn renders_listing_rows() {
+        let mut r = ApartmentRecrod::new();
+        r.id.value = "tr_1".into();
+        r.url.value = "https://www.ss.lv/msg/1.html".into();
+        r.datetime.value = "2023-05-12 14:31:00".into();
+        r.price.value = "600 €/мес.".into();
+        r.district.value = Some("Центр <b>".into());
+        r.distance.value = Some(1500);
+        let params = HashMap::from([
+            ("district".to_string(), "Центр".to_string()),
+            ("sort".to_string(), "price".to_string()),
+        ]);
+        let query = RecordQuery::from_params(&params).unwrap();
+        let page = render_list(&params, &query, &[(r, ListingStatus::Interested)], 1);
+        assert!(page.contains("Центр &lt;b&gt;"));
+        assert!(page.contains(r#"<tr class="interested">"#));
+        assert!(page.contains(r#"action="/listing/tr_1/status""#));
+        assert!(page.contains("Цена ▼"));
+        assert!(page.contains("order=asc"));
+        assert!(!page.contains("дальше"));
+    }
+
+    #[test]
+    fn replaces_url_parameters() {
+        let params = HashMap::from([
+            ("offset".to_string(), "50".to_string()),
+            ("district".to_string(), "Центр".to_string()),
+        ]);
+        assert_eq!(
+            href("/", &params, &[("offset", String::new())]),
+            "/?district=%D0%A6%D0%B5%D0%BD%D1%82%D1%80"
+        );
+    }
+
+    #[test]
+    fn escapes_map_popups() {
+        let mut r = ApartmentRecrod::new();
+        r.url.value = r#"https://www.ss.lv/msg/1.html" onmouseover="alert(1)"#.into();
+        r.latitude.value = Some(56.95);
+        r.longitude.value = Some(24.1);
+        let page = render_map(&HashMap::new(), &[(r, ListingStatus::New)]);
+        assert!(page.contains("1.html&quot; onmouseover=&quot;alert(1)"));
+        assert!(!page.contains(r#"1.html\" onmouseover"#));
+    }
+}
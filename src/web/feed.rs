@@ -0,0 +1,178 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::TimeZone;
+
+use crate::{
+    config::Config,
+    db::{profile::SearchProfile, record::ApartmentRecrod},
+    error::SSError,
+};
+
+use super::{blocking, escape};
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// GET /feed/:profile, the ".atom" suffix is optional
+pub async fn handler(Path(profile): Path<String>, headers: HeaderMap) -> Result<Response, SSError> {
+    let profile = profile.trim_end_matches(".atom").to_string();
+    let name = profile.clone();
+    let records = blocking(move || {
+        SearchProfile::select_by_name(&name)?
+            .ok_or(SSError::NotFound(format!("profile '{}'", name)))?;
+        let since =
+            chrono::Local::now().naive_local() - chrono::Duration::days(Config::feed_days() as i64);
+        ApartmentRecrod::select_recent(
+            &name,
+            &since.format(DATETIME_FORMAT).to_string(),
+            Config::feed_limit(),
+        )
+    })
+    .await?;
+
+    let etag = etag(&records);
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag));
+    let mut response = if cached {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let feed_url = format!("{}/feed/{}", Config::public_url(), profile);
+        (
+            [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+            render(&profile, &feed_url, &records),
+        )
+            .into_response()
+    };
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Ok(cache) = HeaderValue::from_str(&format!(
+        "public, max-age={}",
+        Config::cycle_interval().as_secs()
+    )) {
+        headers.insert(header::CACHE_CONTROL, cache);
+    }
+    Ok(response)
+}
+
+/// Changes whenever an entry is added or changed
+fn etag(records: &[ApartmentRecrod]) -> String {
+    let mut hasher = DefaultHasher::new();
+    for r in records {
+        r.id.value.hash(&mut hasher);
+        r.datetime.value.hash(&mut hasher);
+        r.brief.value.hash(&mut hasher);
+    }
+    format!("\"{:x}\"", hasher.finish())
+}
+
+/// Stored local time in RFC 3339
+fn rfc3339(datetime: &str) -> Option<String> {
+    let naive = chrono::NaiveDateTime::parse_from_str(datetime, DATETIME_FORMAT).ok()?;
+    chrono::Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|t| t.to_rfc3339())
+}
+
+/// "3-комн., 80 м², 600 €/мес."
+pub(crate) fn title(r: &ApartmentRecrod) -> String {
+    let mut parts = vec![];
+    if let Some(rooms) = r.rooms.value {
+        parts.push(format!("{}-комн.", rooms));
+    }
+    if let Some(area) = r.area.value {
+        parts.push(format!("{} м²", area));
+    }
+    parts.push(r.price.value.clone());
+    [
+        parts.join(", "),
+        r.address.value.clone().unwrap_or_default(),
+    ]
+    .into_iter()
+    .filter(|p| !p.is_empty())
+    .collect::<Vec<_>>()
+    .join(" — ")
+}
+
+/// Atom feed of the records, newest first
+pub fn render(profile: &str, feed_url: &str, records: &[ApartmentRecrod]) -> String {
+    let updated = records
+        .first()
+        .and_then(|r| rfc3339(&r.datetime.value))
+        .unwrap_or_else(|| chrono::Local::now().to_rfc3339());
+    let mut feed = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>ss.lv: {}</title>
+<id>{}</id>
+<link rel="self" href="{}"/>
+<updated>{}</updated>
+<author><name>rentbot_sslv</name></author>
+"#,
+        escape(profile),
+        escape(feed_url),
+        escape(feed_url),
+        updated
+    );
+    for r in records {
+        let published = rfc3339(&r.datetime.value).unwrap_or_else(|| updated.clone());
+        let content = match r.description.value.as_ref() {
+            Some(description) => format!("{}\n\n{}", r.brief.value, description),
+            None => r.brief.value.clone(),
+        };
+        feed.push_str(&format!(
+            r#"<entry>
+<title>{}</title>
+<id>{}</id>
+<link href="{}"/>
+<published>{}</published>
+<updated>{}</updated>
+<content type="text">{}</content>
+</entry>
+"#,
+            escape(&title(r)),
+            escape(&r.url.value),
+            escape(&r.url.value),
+            published,
+            published,
+            escape(&content)
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_entries() {
+        let mut r = ApartmentRecrod::new();
+        r.id.value = "tr_1".into();
+        r.url.value = "https://www.ss.lv/msg/1.html?a=1&b=2".into();
+        r.datetime.value = "2023-05-12 14:31:00".into();
+        r.price.value = "600 €/мес.".into();
+        r.rooms.value = Some(3);
+        r.area.value = Some(80.0);
+        r.brief.value = "цена:600 <б>".into();
+        r.description.value = Some("Светлая квартира".into());
+        let feed = render("default", "http://localhost/feed/default", &[r]);
+        assert!(feed.contains("<title>3-комн., 80 м², 600 €/мес.</title>"));
+        assert!(feed.contains("<link href=\"https://www.ss.lv/msg/1.html?a=1&amp;b=2\"/>"));
+        assert!(feed.contains("<published>2023-05-12T14:31:00"));
+        assert!(feed.contains("цена:600 &lt;б&gt;\n\nСветлая квартира</content>"));
+        assert_eq!(feed.matches("<entry>").count(), 1);
+    }
+}